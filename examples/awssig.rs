@@ -1,13 +1,8 @@
 use http::HeaderMap;
-use ngx::ffi::{
-    nginx_version, ngx_array_push, ngx_command_t, ngx_conf_t, ngx_http_core_module, ngx_http_handler_pt,
-    ngx_http_module_t, ngx_http_phases_NGX_HTTP_PRECONTENT_PHASE, ngx_http_request_t, ngx_int_t, ngx_module_t,
-    ngx_str_t, ngx_uint_t, NGX_CONF_TAKE1, NGX_HTTP_LOC_CONF, NGX_HTTP_MODULE, NGX_HTTP_SRV_CONF,
-    NGX_RS_HTTP_LOC_CONF_OFFSET, NGX_RS_MODULE_SIGNATURE,
-};
+use ngx::core::NgxStr;
+use ngx::ffi::{ngx_conf_t, ngx_http_request_t, ngx_int_t};
 use ngx::{core, core::Status, http::*};
-use ngx::{http_request_handler, ngx_log_debug_http, ngx_modules, ngx_null_command, ngx_string};
-use std::os::raw::{c_char, c_void};
+use ngx::{http_request_handler, ngx_http_commands, ngx_http_module, ngx_log_debug_http};
 
 struct Module;
 
@@ -17,270 +12,115 @@ impl HTTPModule for Module {
     type LocConf = ModuleConfig;
 
     unsafe extern "C" fn postconfiguration(cf: *mut ngx_conf_t) -> ngx_int_t {
-        let cmcf = ngx_http_conf_get_module_main_conf(cf, &ngx_http_core_module);
-
-        let h = ngx_array_push(&mut (*cmcf).phases[ngx_http_phases_NGX_HTTP_PRECONTENT_PHASE as usize].handlers)
-            as *mut ngx_http_handler_pt;
-        if h.is_null() {
-            return core::Status::NGX_ERROR.into();
-        }
         // set an phase handler
-        *h = Some(awssigv4_header_handler);
-        core::Status::NGX_OK.into()
+        register_phase_handler(&mut *cf, Phase::PreContent, awssigv4_header_handler).into()
     }
 }
 
 #[derive(Debug, Default)]
 struct ModuleConfig {
-    enable: bool,
-    access_key: String,
-    secret_key: String,
-    s3_bucket: String,
-    s3_endpoint: String,
+    enable: Option<bool>,
+    access_key: Option<String>,
+    secret_key: Option<String>,
+    s3_bucket: Option<String>,
+    s3_endpoint: Option<String>,
 }
 
-#[no_mangle]
-static mut ngx_http_awssigv4_commands: [ngx_command_t; 6] = [
-    ngx_command_t {
-        name: ngx_string!("awssigv4"),
-        type_: (NGX_HTTP_LOC_CONF | NGX_HTTP_SRV_CONF | NGX_CONF_TAKE1) as ngx_uint_t,
-        set: Some(ngx_http_awssigv4_commands_set_enable),
-        conf: NGX_RS_HTTP_LOC_CONF_OFFSET,
-        offset: 0,
-        post: std::ptr::null_mut(),
-    },
-    ngx_command_t {
-        name: ngx_string!("awssigv4_access_key"),
-        type_: (NGX_HTTP_LOC_CONF | NGX_HTTP_SRV_CONF | NGX_CONF_TAKE1) as ngx_uint_t,
-        set: Some(ngx_http_awssigv4_commands_set_access_key),
-        conf: NGX_RS_HTTP_LOC_CONF_OFFSET,
-        offset: 0,
-        post: std::ptr::null_mut(),
-    },
-    ngx_command_t {
-        name: ngx_string!("awssigv4_secret_key"),
-        type_: (NGX_HTTP_LOC_CONF | NGX_HTTP_SRV_CONF | NGX_CONF_TAKE1) as ngx_uint_t,
-        set: Some(ngx_http_awssigv4_commands_set_secret_key),
-        conf: NGX_RS_HTTP_LOC_CONF_OFFSET,
-        offset: 0,
-        post: std::ptr::null_mut(),
-    },
-    ngx_command_t {
-        name: ngx_string!("awssigv4_s3_bucket"),
-        type_: (NGX_HTTP_LOC_CONF | NGX_HTTP_SRV_CONF | NGX_CONF_TAKE1) as ngx_uint_t,
-        set: Some(ngx_http_awssigv4_commands_set_s3_bucket),
-        conf: NGX_RS_HTTP_LOC_CONF_OFFSET,
-        offset: 0,
-        post: std::ptr::null_mut(),
-    },
-    ngx_command_t {
-        name: ngx_string!("awssigv4_s3_endpoint"),
-        type_: (NGX_HTTP_LOC_CONF | NGX_HTTP_SRV_CONF | NGX_CONF_TAKE1) as ngx_uint_t,
-        set: Some(ngx_http_awssigv4_commands_set_s3_endpoint),
-        conf: NGX_RS_HTTP_LOC_CONF_OFFSET,
-        offset: 0,
-        post: std::ptr::null_mut(),
-    },
-    ngx_null_command!(),
-];
-
-#[no_mangle]
-static ngx_http_awssigv4_module_ctx: ngx_http_module_t = ngx_http_module_t {
-    preconfiguration: Some(Module::preconfiguration),
-    postconfiguration: Some(Module::postconfiguration),
-    create_main_conf: Some(Module::create_main_conf),
-    init_main_conf: Some(Module::init_main_conf),
-    create_srv_conf: Some(Module::create_srv_conf),
-    merge_srv_conf: Some(Module::merge_srv_conf),
-    create_loc_conf: Some(Module::create_loc_conf),
-    merge_loc_conf: Some(Module::merge_loc_conf),
-};
-
-ngx_modules!(ngx_http_awssigv4_module);
-
-#[no_mangle]
-pub static mut ngx_http_awssigv4_module: ngx_module_t = ngx_module_t {
-    ctx_index: ngx_uint_t::max_value(),
-    index: ngx_uint_t::max_value(),
-    name: std::ptr::null_mut(),
-    spare0: 0,
-    spare1: 0,
-    version: nginx_version as ngx_uint_t,
-    signature: NGX_RS_MODULE_SIGNATURE.as_ptr() as *const c_char,
-
-    ctx: &ngx_http_awssigv4_module_ctx as *const _ as *mut _,
-    commands: unsafe { &ngx_http_awssigv4_commands[0] as *const _ as *mut _ },
-    type_: NGX_HTTP_MODULE as ngx_uint_t,
-
-    init_master: None,
-    init_module: None,
-    init_process: None,
-    init_thread: None,
-    exit_thread: None,
-    exit_process: None,
-    exit_master: None,
+ngx_http_commands! {
+    #[no_mangle]
+    static mut ngx_http_awssigv4_commands = [
+        {
+            name: "awssigv4",
+            context: [LOC, SRV],
+            args: [FLAG],
+            conf: LOC,
+            slot: FlagSlot => (ModuleConfig, enable),
+        },
+        {
+            name: "awssigv4_access_key",
+            context: [LOC, SRV],
+            args: [TAKE1],
+            conf: LOC,
+            slot: StrSlot => (ModuleConfig, access_key),
+        },
+        {
+            name: "awssigv4_secret_key",
+            context: [LOC, SRV],
+            args: [TAKE1],
+            conf: LOC,
+            slot: StrSlot => (ModuleConfig, secret_key),
+        },
+        {
+            name: "awssigv4_s3_bucket",
+            context: [LOC, SRV],
+            args: [TAKE1],
+            conf: LOC,
+            set: ngx_http_awssigv4_commands_set_s3_bucket,
+        },
+        {
+            name: "awssigv4_s3_endpoint",
+            context: [LOC, SRV],
+            args: [TAKE1],
+            conf: LOC,
+            slot: StrSlot => (ModuleConfig, s3_endpoint),
+        },
+    ];
+}
 
-    spare_hook0: 0,
-    spare_hook1: 0,
-    spare_hook2: 0,
-    spare_hook3: 0,
-    spare_hook4: 0,
-    spare_hook5: 0,
-    spare_hook6: 0,
-    spare_hook7: 0,
-};
+ngx_http_module!(ngx_http_awssigv4_module, Module, commands = ngx_http_awssigv4_commands);
 
 impl Merge for ModuleConfig {
     fn merge(&mut self, prev: &ModuleConfig) -> Result<(), MergeConfigError> {
-        if prev.enable {
-            self.enable = true;
-        };
-
-        if self.access_key.is_empty() {
-            self.access_key = String::from(if !prev.access_key.is_empty() {
-                &prev.access_key
-            } else {
-                ""
-            });
-        }
-        if self.enable && self.access_key.is_empty() {
-            return Err(MergeConfigError::NoValue);
-        }
-
-        if self.secret_key.is_empty() {
-            self.secret_key = String::from(if !prev.secret_key.is_empty() {
-                &prev.secret_key
-            } else {
-                ""
-            });
-        }
-        if self.enable && self.secret_key.is_empty() {
+        merge_conf_value(&mut self.enable, &prev.enable, false);
+        merge_conf_option(&mut self.access_key, &prev.access_key);
+        merge_conf_option(&mut self.secret_key, &prev.secret_key);
+        merge_conf_option(&mut self.s3_bucket, &prev.s3_bucket);
+        merge_conf_value(&mut self.s3_endpoint, &prev.s3_endpoint, "s3.amazonaws.com".to_string());
+
+        if self.enable == Some(true)
+            && (self.access_key.is_none() || self.secret_key.is_none() || self.s3_bucket.is_none())
+        {
             return Err(MergeConfigError::NoValue);
         }
-
-        if self.s3_bucket.is_empty() {
-            self.s3_bucket = String::from(if !prev.s3_bucket.is_empty() {
-                &prev.s3_bucket
-            } else {
-                ""
-            });
-        }
-        if self.enable && self.s3_bucket.is_empty() {
-            return Err(MergeConfigError::NoValue);
-        }
-
-        if self.s3_endpoint.is_empty() {
-            self.s3_endpoint = String::from(if !prev.s3_endpoint.is_empty() {
-                &prev.s3_endpoint
-            } else {
-                "s3.amazonaws.com"
-            });
-        }
         Ok(())
     }
 }
 
-#[no_mangle]
-extern "C" fn ngx_http_awssigv4_commands_set_enable(
-    cf: *mut ngx_conf_t,
-    _cmd: *mut ngx_command_t,
-    conf: *mut c_void,
-) -> *mut c_char {
-    unsafe {
-        let conf = &mut *(conf as *mut ModuleConfig);
-        let args = (*(*cf).args).elts as *mut ngx_str_t;
-        let val = (*args.add(1)).to_str();
-
-        // set default value optionally
-        conf.enable = false;
-
-        if val.len() == 2 && val.eq_ignore_ascii_case("on") {
-            conf.enable = true;
-        } else if val.len() == 3 && val.eq_ignore_ascii_case("off") {
-            conf.enable = false;
-        }
-    };
-
-    std::ptr::null_mut()
-}
-
-#[no_mangle]
-extern "C" fn ngx_http_awssigv4_commands_set_access_key(
-    cf: *mut ngx_conf_t,
-    _cmd: *mut ngx_command_t,
-    conf: *mut c_void,
-) -> *mut c_char {
-    unsafe {
-        let conf = &mut *(conf as *mut ModuleConfig);
-        let args = (*(*cf).args).elts as *mut ngx_str_t;
-        conf.access_key = (*args.add(1)).to_string();
-    };
-
-    std::ptr::null_mut()
-}
-
-#[no_mangle]
-extern "C" fn ngx_http_awssigv4_commands_set_secret_key(
-    cf: *mut ngx_conf_t,
-    _cmd: *mut ngx_command_t,
-    conf: *mut c_void,
-) -> *mut c_char {
-    unsafe {
-        let conf = &mut *(conf as *mut ModuleConfig);
-        let args = (*(*cf).args).elts as *mut ngx_str_t;
-        conf.secret_key = (*args.add(1)).to_string();
-    };
-
-    std::ptr::null_mut()
-}
-
-#[no_mangle]
-extern "C" fn ngx_http_awssigv4_commands_set_s3_bucket(
-    cf: *mut ngx_conf_t,
-    _cmd: *mut ngx_command_t,
-    conf: *mut c_void,
-) -> *mut c_char {
-    unsafe {
-        let conf = &mut *(conf as *mut ModuleConfig);
-        let args = (*(*cf).args).elts as *mut ngx_str_t;
-        conf.s3_bucket = (*args.add(1)).to_string();
-        if conf.s3_bucket.len() == 1 {
-            println!("Validation failed");
-            return ngx::core::NGX_CONF_ERROR as _;
-        }
-    };
-    std::ptr::null_mut()
-}
-
-#[no_mangle]
-extern "C" fn ngx_http_awssigv4_commands_set_s3_endpoint(
-    cf: *mut ngx_conf_t,
-    _cmd: *mut ngx_command_t,
-    conf: *mut c_void,
-) -> *mut c_char {
-    unsafe {
-        let conf = &mut *(conf as *mut ModuleConfig);
-        let args = (*(*cf).args).elts as *mut ngx_str_t;
-        conf.s3_endpoint = (*args.add(1)).to_string();
-    };
-
-    std::ptr::null_mut()
+fn ngx_http_awssigv4_commands_set_s3_bucket(
+    cf: &mut ngx_conf_t,
+    conf: &mut ModuleConfig,
+    args: &[&NgxStr],
+) -> Result<(), DirectiveError> {
+    StrSlot::set(cf, &mut conf.s3_bucket, args)?;
+    if conf.s3_bucket.as_ref().is_some_and(|bucket| bucket.len() == 1) {
+        return Err(DirectiveError::Message("Validation failed".to_string()));
+    }
+    Ok(())
 }
 
 http_request_handler!(awssigv4_header_handler, |request: &mut Request| {
     // get Module Config from request
     let conf = unsafe { request.get_module_loc_conf::<ModuleConfig>(&ngx_http_awssigv4_module) };
     let conf = conf.unwrap();
+    let enable = conf.enable.unwrap_or(false);
     ngx_log_debug_http!(request, "AWS signature V4 module {}", {
-        if conf.enable {
+        if enable {
             "enabled"
         } else {
             "disabled"
         }
     });
-    if !conf.enable {
+    if !enable {
         return core::Status::NGX_DECLINED;
     }
+    let (Some(access_key), Some(secret_key), Some(s3_bucket), Some(s3_endpoint)) = (
+        conf.access_key.as_deref(),
+        conf.secret_key.as_deref(),
+        conf.s3_bucket.as_deref(),
+        conf.s3_endpoint.as_deref(),
+    ) else {
+        return core::Status::NGX_DECLINED;
+    };
 
     // TODO: build url properly from the original URL from client
     let method = request.method();
@@ -290,7 +130,7 @@ http_request_handler!(awssigv4_header_handler, |request: &mut Request| {
 
     let datetime = chrono::Utc::now();
     let uri = match request.unparsed_uri().to_str() {
-        Ok(v) => format!("https://{}.{}{}", conf.s3_bucket, conf.s3_endpoint, v),
+        Ok(v) => format!("https://{}.{}{}", s3_bucket, s3_endpoint, v),
         Err(_) => return core::Status::NGX_DECLINED,
     };
 
@@ -322,8 +162,8 @@ http_request_handler!(awssigv4_header_handler, |request: &mut Request| {
             &datetime,
             &headers,
             "us-east-1",
-            conf.access_key.as_str(),
-            conf.secret_key.as_str(),
+            access_key,
+            secret_key,
             "s3",
             "",
         );
@@ -1,150 +1,21 @@
 use ngx::ffi::{
-    in_port_t, nginx_version, ngx_conf_t, ngx_connection_local_sockaddr, ngx_http_add_variable, ngx_http_module_t,
-    ngx_http_request_t, ngx_http_variable_t, ngx_inet_get_port, ngx_int_t, ngx_module_t, ngx_sock_ntop, ngx_str_t,
-    ngx_uint_t, ngx_variable_value_t, sockaddr, sockaddr_storage, INET_ADDRSTRLEN, NGX_HTTP_MODULE,
-    NGX_RS_MODULE_SIGNATURE,
+    in_port_t, ngx_conf_t, ngx_connection_local_sockaddr, ngx_inet_get_port, ngx_int_t, ngx_sock_ntop, sockaddr,
+    sockaddr_storage, INET_ADDRSTRLEN,
 };
-use ngx::{core, core::Status, http, http::HTTPModule};
-use ngx::{http_variable_get, ngx_http_null_variable, ngx_log_debug_http, ngx_modules, ngx_null_string, ngx_string};
-use std::os::raw::{c_char, c_int, c_void};
+use ngx::{core, http, http::HTTPModule};
+use ngx::{ngx_http_module, ngx_log_debug_http};
+use std::os::raw::{c_int, c_void};
+use std::ptr::addr_of;
 
 const IPV4_STRLEN: usize = INET_ADDRSTRLEN as usize;
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 struct NgxHttpOrigDstCtx {
-    orig_dst_addr: ngx_str_t,
-    orig_dst_port: ngx_str_t,
+    orig_dst_addr: String,
+    orig_dst_port: String,
 }
 
-impl Default for NgxHttpOrigDstCtx {
-    fn default() -> NgxHttpOrigDstCtx {
-        NgxHttpOrigDstCtx {
-            orig_dst_addr: ngx_null_string!(),
-            orig_dst_port: ngx_null_string!(),
-        }
-    }
-}
-
-impl NgxHttpOrigDstCtx {
-    pub fn save(&mut self, addr: &str, port: in_port_t, pool: &mut core::Pool) -> core::Status {
-        let addr_data = pool.alloc(IPV4_STRLEN);
-        if addr_data.is_null() {
-            return core::Status::NGX_ERROR;
-        }
-        unsafe { libc::memcpy(addr_data, addr.as_ptr() as *const c_void, IPV4_STRLEN) };
-        self.orig_dst_addr.len = IPV4_STRLEN;
-        self.orig_dst_addr.data = addr_data as *mut u8;
-
-        let port_str = port.to_string();
-        let port_data = pool.alloc(port_str.len());
-        if port_data.is_null() {
-            return core::Status::NGX_ERROR;
-        }
-        unsafe { libc::memcpy(port_data, port_str.as_bytes().as_ptr() as *const c_void, port_str.len()) };
-        self.orig_dst_port.len = port_str.len();
-        self.orig_dst_port.data = port_data as *mut u8;
-
-        core::Status::NGX_OK
-    }
-
-    pub unsafe fn bind_addr(&self, v: *mut ngx_variable_value_t) {
-        if self.orig_dst_addr.len == 0 {
-            (*v).set_not_found(1);
-            return;
-        }
-
-        (*v).set_valid(1);
-        (*v).set_no_cacheable(0);
-        (*v).set_not_found(0);
-        (*v).set_len(self.orig_dst_addr.len as u32);
-        (*v).data = self.orig_dst_addr.data;
-    }
-
-    pub unsafe fn bind_port(&self, v: *mut ngx_variable_value_t) {
-        if self.orig_dst_port.len == 0 {
-            (*v).set_not_found(1);
-            return;
-        }
-
-        (*v).set_valid(1);
-        (*v).set_no_cacheable(0);
-        (*v).set_not_found(0);
-        (*v).set_len(self.orig_dst_port.len as u32);
-        (*v).data = self.orig_dst_port.data;
-    }
-}
-
-#[no_mangle]
-static ngx_http_orig_dst_module_ctx: ngx_http_module_t = ngx_http_module_t {
-    preconfiguration: Some(Module::preconfiguration),
-    postconfiguration: Some(Module::postconfiguration),
-    create_main_conf: Some(Module::create_main_conf),
-    init_main_conf: Some(Module::init_main_conf),
-    create_srv_conf: Some(Module::create_srv_conf),
-    merge_srv_conf: Some(Module::merge_srv_conf),
-    create_loc_conf: Some(Module::create_loc_conf),
-    merge_loc_conf: Some(Module::merge_loc_conf),
-};
-
-ngx_modules!(ngx_http_orig_dst_module);
-
-#[no_mangle]
-pub static mut ngx_http_orig_dst_module: ngx_module_t = ngx_module_t {
-    ctx_index: ngx_uint_t::max_value(),
-    index: ngx_uint_t::max_value(),
-    name: std::ptr::null_mut(),
-    spare0: 0,
-    spare1: 0,
-    version: nginx_version as ngx_uint_t,
-    signature: NGX_RS_MODULE_SIGNATURE.as_ptr() as *const c_char,
-    ctx: &ngx_http_orig_dst_module_ctx as *const _ as *mut _,
-    commands: std::ptr::null_mut(),
-    type_: NGX_HTTP_MODULE as ngx_uint_t,
-
-    init_master: None,
-    init_module: None,
-    init_process: None,
-    init_thread: None,
-    exit_thread: None,
-    exit_process: None,
-    exit_master: None,
-
-    spare_hook0: 0,
-    spare_hook1: 0,
-    spare_hook2: 0,
-    spare_hook3: 0,
-    spare_hook4: 0,
-    spare_hook5: 0,
-    spare_hook6: 0,
-    spare_hook7: 0,
-};
-
-#[no_mangle]
-static mut ngx_http_orig_dst_vars: [ngx_http_variable_t; 3] = [
-    // ngx_str_t name
-    // ngx_http_set_variable_pt set_handler
-    // ngx_http_get_variable_pt get_handler
-    // uintptr_t data
-    // ngx_uint_t flags
-    // ngx_uint_t index
-    ngx_http_variable_t {
-        name: ngx_string!("server_orig_addr"),
-        set_handler: None,
-        get_handler: Some(ngx_http_orig_dst_addr_variable),
-        data: 0,
-        flags: 0,
-        index: 0,
-    },
-    ngx_http_variable_t {
-        name: ngx_string!("server_orig_port"),
-        set_handler: None,
-        get_handler: Some(ngx_http_orig_dst_port_variable),
-        data: 0,
-        flags: 0,
-        index: 0,
-    },
-    ngx_http_null_variable!(),
-];
+ngx_http_module!(ngx_http_orig_dst_module, Module);
 
 unsafe fn ngx_get_origdst(request: &mut http::Request) -> Result<(String, in_port_t), core::Status> {
     let c = request.connection();
@@ -199,89 +70,34 @@ unsafe fn ngx_get_origdst(request: &mut http::Request) -> Result<(String, in_por
         ngx_log_debug_http!(request, "httporigdst: ngx_sock_ntop failed to convert sockaddr");
         return Err(core::Status::NGX_ERROR);
     }
+    ip.truncate(e);
 
     let port = unsafe { ngx_inet_get_port(std::ptr::addr_of_mut!(addr) as *mut sockaddr) };
 
     Ok((String::from_utf8(ip).unwrap(), port))
 }
 
-http_variable_get!(
-    ngx_http_orig_dst_addr_variable,
-    |request: &mut http::Request, v: *mut ngx_variable_value_t, _: usize| {
-        let ctx = request.get_module_ctx::<NgxHttpOrigDstCtx>(&ngx_http_orig_dst_module);
-        if let Some(obj) = ctx {
-            ngx_log_debug_http!(request, "httporigdst: found context and binding variable",);
-            obj.bind_addr(v);
-            return core::Status::NGX_OK;
-        }
-        // lazy initialization:
-        //   get original dest information
-        //   create context
-        //   set context
-        // bind address
+// Get the original destination from the request context, creating it on the first use.
+fn ngx_http_orig_dst_ctx(request: &mut http::Request) -> Option<&NgxHttpOrigDstCtx> {
+    if request
+        .get_module_ctx::<NgxHttpOrigDstCtx>(unsafe { &*addr_of!(ngx_http_orig_dst_module) })
+        .is_none()
+    {
         ngx_log_debug_http!(request, "httporigdst: context not found, getting address");
-        let r = ngx_get_origdst(request);
-        match r {
-            Err(e) => {
-                return e;
-            }
-            Ok((ip, port)) => {
-                // create context,
-                // set context
-                let new_ctx = request.pool().allocate::<NgxHttpOrigDstCtx>(Default::default());
-
-                if new_ctx.is_null() {
-                    return core::Status::NGX_ERROR;
-                }
-
-                ngx_log_debug_http!(request, "httporigdst: saving ip - {:?}, port - {}", ip, port,);
-                (*new_ctx).save(&ip, port, &mut request.pool());
-                (*new_ctx).bind_addr(v);
-                request.set_module_ctx(new_ctx as *mut c_void, &ngx_http_orig_dst_module);
-            }
+        let (ip, port) = unsafe { ngx_get_origdst(request) }.ok()?;
+
+        ngx_log_debug_http!(request, "httporigdst: saving ip - {:?}, port - {}", ip, port,);
+        let new_ctx = request.pool().allocate(NgxHttpOrigDstCtx {
+            orig_dst_addr: ip,
+            orig_dst_port: port.to_string(),
+        });
+        if new_ctx.is_null() {
+            return None;
         }
-        core::Status::NGX_OK
+        request.set_module_ctx(new_ctx as *mut c_void, unsafe { &*addr_of!(ngx_http_orig_dst_module) });
     }
-);
-
-http_variable_get!(
-    ngx_http_orig_dst_port_variable,
-    |request: &mut http::Request, v: *mut ngx_variable_value_t, _: usize| {
-        let ctx = request.get_module_ctx::<NgxHttpOrigDstCtx>(&ngx_http_orig_dst_module);
-        if let Some(obj) = ctx {
-            ngx_log_debug_http!(request, "httporigdst: found context and binding variable",);
-            obj.bind_port(v);
-            return core::Status::NGX_OK;
-        }
-        // lazy initialization:
-        //   get original dest information
-        //   create context
-        //   set context
-        // bind port
-        ngx_log_debug_http!(request, "httporigdst: context not found, getting address");
-        let r = ngx_get_origdst(request);
-        match r {
-            Err(e) => {
-                return e;
-            }
-            Ok((ip, port)) => {
-                // create context,
-                // set context
-                let new_ctx = request.pool().allocate::<NgxHttpOrigDstCtx>(Default::default());
-
-                if new_ctx.is_null() {
-                    return core::Status::NGX_ERROR;
-                }
-
-                ngx_log_debug_http!(request, "httporigdst: saving ip - {:?}, port - {}", ip, port,);
-                (*new_ctx).save(&ip, port, &mut request.pool());
-                (*new_ctx).bind_port(v);
-                request.set_module_ctx(new_ctx as *mut c_void, &ngx_http_orig_dst_module);
-            }
-        }
-        core::Status::NGX_OK
-    }
-);
+    request.get_module_ctx::<NgxHttpOrigDstCtx>(unsafe { &*addr_of!(ngx_http_orig_dst_module) })
+}
 
 struct Module;
 
@@ -290,19 +106,23 @@ impl HTTPModule for Module {
     type SrvConf = ();
     type LocConf = ();
 
-    // static ngx_int_t ngx_http_orig_dst_add_variables(ngx_conf_t *cf)
     unsafe extern "C" fn preconfiguration(cf: *mut ngx_conf_t) -> ngx_int_t {
-        for mut v in ngx_http_orig_dst_vars {
-            if v.name.len == 0 {
-                break;
-            }
-            let var = ngx_http_add_variable(cf, &mut v.name, v.flags);
-            if var.is_null() {
-                return core::Status::NGX_ERROR.into();
-            }
-            (*var).get_handler = v.get_handler;
-            (*var).data = v.data;
+        let cf = &mut *cf;
+
+        let rc = http::VariableBuilder::new("server_orig_addr")
+            .getter(|request: &mut http::Request| {
+                ngx_http_orig_dst_ctx(request).map(|ctx| ctx.orig_dst_addr.as_str().into())
+            })
+            .register(cf);
+        if rc != core::Status::NGX_OK {
+            return rc.into();
         }
-        core::Status::NGX_OK.into()
+
+        http::VariableBuilder::new("server_orig_port")
+            .getter(|request: &mut http::Request| {
+                ngx_http_orig_dst_ctx(request).map(|ctx| ctx.orig_dst_port.as_str().into())
+            })
+            .register(cf)
+            .into()
     }
 }
@@ -0,0 +1,98 @@
+use ngx::core::{merge_conf_value, FlagSlot, Merge, MergeConfigError};
+use ngx::ffi::{
+    ngx_conf_t, ngx_int_t, ngx_stream_phases_NGX_STREAM_ACCESS_PHASE, ngx_stream_variable_t, ngx_variable_value_t,
+    NGX_STREAM_FORBIDDEN,
+};
+use ngx::stream::{StreamModule, StreamSession};
+use ngx::{core, core::Status, stream};
+use ngx::{ngx_log_debug_stream, ngx_stream_commands, ngx_stream_module, ngx_stream_null_variable, ngx_string};
+use ngx::{stream_session_handler, stream_variable_get};
+
+struct Module;
+
+impl StreamModule for Module {
+    type MainConf = ();
+    type SrvConf = SrvConfig;
+
+    unsafe extern "C" fn postconfiguration(cf: *mut ngx_conf_t) -> ngx_int_t {
+        // set an Access phase handler
+        stream::add_phase_handler(
+            cf,
+            ngx_stream_phases_NGX_STREAM_ACCESS_PHASE,
+            Some(stream_deny_access_handler),
+        )
+        .into()
+    }
+}
+
+#[derive(Debug, Default)]
+struct SrvConfig {
+    deny: Option<bool>,
+}
+
+impl Merge for SrvConfig {
+    fn merge(&mut self, prev: &SrvConfig) -> Result<(), MergeConfigError> {
+        merge_conf_value(&mut self.deny, &prev.deny, false);
+        Ok(())
+    }
+}
+
+ngx_stream_commands! {
+    #[no_mangle]
+    static mut ngx_stream_deny_commands = [
+        {
+            name: "stream_deny",
+            context: [MAIN, SRV],
+            args: [FLAG],
+            conf: SRV,
+            slot: FlagSlot => (SrvConfig, deny),
+        },
+    ];
+}
+
+#[no_mangle]
+static mut ngx_stream_deny_vars: [ngx_stream_variable_t; 2] = [
+    ngx_stream_variable_t {
+        name: ngx_string!("stream_deny"),
+        set_handler: None,
+        get_handler: Some(stream_deny_variable),
+        data: 0,
+        flags: 0,
+        index: 0,
+    },
+    ngx_stream_null_variable!(),
+];
+
+ngx_stream_module!(
+    ngx_stream_deny_module,
+    Module,
+    commands = ngx_stream_deny_commands,
+    variables = ngx_stream_deny_vars
+);
+
+fn is_denied(session: &StreamSession) -> bool {
+    let conf = unsafe { session.get_module_srv_conf::<SrvConfig>(&*std::ptr::addr_of!(ngx_stream_deny_module)) };
+    conf.and_then(|conf| conf.deny).unwrap_or(false)
+}
+
+stream_session_handler!(stream_deny_access_handler, |session: &mut StreamSession| {
+    if !is_denied(session) {
+        return core::Status::NGX_DECLINED;
+    }
+
+    ngx_log_debug_stream!(session, "stream_deny: rejecting connection");
+    Status(NGX_STREAM_FORBIDDEN as ngx_int_t)
+});
+
+stream_variable_get!(
+    stream_deny_variable,
+    |session: &mut StreamSession, v: *mut ngx_variable_value_t, _: usize| {
+        let value: &'static [u8] = if is_denied(session) { b"on" } else { b"off" };
+        (*v).set_valid(1);
+        (*v).set_no_cacheable(0);
+        (*v).set_not_found(0);
+        (*v).set_len(value.len() as u32);
+        (*v).data = value.as_ptr() as *mut u8;
+        core::Status::NGX_OK
+    }
+);
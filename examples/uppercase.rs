@@ -0,0 +1,129 @@
+use std::slice;
+
+use ngx::core::{Buffer, Chain, FlagSlot, Merge, MergeConfigError, Pool, Status, TemporaryBuffer};
+use ngx::ffi::{ngx_buf_t, ngx_conf_t, ngx_int_t};
+use ngx::http::{self, HttpFilter, NextBodyFilter, NextHeaderFilter, Request};
+use ngx::{http_filter, ngx_http_commands, ngx_http_module, ngx_log_debug_http};
+
+struct Module;
+
+impl http::HTTPModule for Module {
+    type MainConf = ();
+    type SrvConf = ();
+    type LocConf = ModuleConfig;
+
+    unsafe extern "C" fn postconfiguration(_cf: *mut ngx_conf_t) -> ngx_int_t {
+        ngx_http_uppercase_filter_init().into()
+    }
+}
+
+#[derive(Debug, Default)]
+struct ModuleConfig {
+    enable: Option<bool>,
+}
+
+impl Merge for ModuleConfig {
+    fn merge(&mut self, prev: &ModuleConfig) -> Result<(), MergeConfigError> {
+        http::merge_conf_value(&mut self.enable, &prev.enable, false);
+        Ok(())
+    }
+}
+
+ngx_http_commands! {
+    #[no_mangle]
+    static mut ngx_http_uppercase_commands = [
+        {
+            name: "uppercase",
+            context: [MAIN, SRV, LOC],
+            args: [FLAG],
+            conf: LOC,
+            slot: FlagSlot => (ModuleConfig, enable),
+        },
+    ];
+}
+
+// Loaded before the copy filter, so that static files and responses buffered to temporary files
+// are read into memory before reaching the body filter.
+ngx_http_module!(
+    ngx_http_uppercase_module,
+    Module,
+    commands = ngx_http_uppercase_commands,
+    filter = true
+);
+
+fn is_enabled(request: &Request) -> bool {
+    let conf = unsafe { request.get_module_loc_conf::<ModuleConfig>(&*std::ptr::addr_of!(ngx_http_uppercase_module)) };
+    conf.and_then(|conf| conf.enable).unwrap_or(false)
+}
+
+struct UppercaseFilter;
+
+impl HttpFilter for UppercaseFilter {
+    fn header_filter(request: &mut Request, next: &NextHeaderFilter) -> Status {
+        if is_enabled(request) {
+            ngx_log_debug_http!(request, "uppercase filter enabled");
+            request.set_filter_need_in_memory();
+        }
+        next.call(request)
+    }
+
+    fn body_filter(request: &mut Request, chain: &mut Chain, next: &NextBodyFilter) -> Status {
+        if !is_enabled(request) {
+            return next.call(request, chain);
+        }
+
+        // The input buffers belong to the previous filters, which reuse them once consumed, so
+        // the output is written to new buffers.
+        let mut pool = request.pool();
+        let mut out = Chain::new();
+        for buf in chain.iter_mut() {
+            // SAFETY: each link of the chain has a valid buffer.
+            let buf = unsafe { &mut *buf.buf };
+            let Some(copy) = uppercase(&mut pool, buf) else {
+                return Status::NGX_ERROR;
+            };
+            if out.push(&mut pool, copy).is_none() {
+                return Status::NGX_ERROR;
+            }
+        }
+        next.call(request, &mut out)
+    }
+}
+
+http_filter!(ngx_http_uppercase_filter_init, UppercaseFilter);
+
+/// Copy `buf` in uppercase, with the same flags, and mark it as consumed.
+///
+/// Returns `None` if the buffer is not in memory or the copy cannot be allocated.
+fn uppercase(pool: &mut Pool, buf: &mut ngx_buf_t) -> Option<TemporaryBuffer> {
+    let in_memory = buf.temporary() != 0 || buf.memory() != 0 || buf.mmap() != 0;
+    if buf.in_file() != 0 && !in_memory {
+        return None;
+    }
+    let data = if in_memory && buf.last > buf.pos {
+        unsafe { slice::from_raw_parts(buf.pos, buf.last as usize - buf.pos as usize) }
+    } else {
+        &[]
+    };
+
+    let mut copy = pool.create_buffer(data.len())?;
+    let b = copy.as_ngx_buf_mut();
+    unsafe {
+        for (i, c) in data.iter().enumerate() {
+            *(*b).last.add(i) = c.to_ascii_uppercase();
+        }
+        (*b).last = (*b).last.add(data.len());
+        // An empty buffer must not be marked as in memory, to be handled as a special buffer.
+        (*b).set_temporary(!data.is_empty() as _);
+        (*b).set_flush(buf.flush());
+        (*b).set_sync(buf.sync());
+        (*b).set_last_buf(buf.last_buf());
+        (*b).set_last_in_chain(buf.last_in_chain());
+    }
+
+    buf.pos = buf.last;
+    if buf.in_file() != 0 {
+        buf.file_pos = buf.file_last;
+    }
+    Some(copy)
+}
@@ -68,6 +68,8 @@ const NGX_BASE_MODULES: [&str; 20] = [
     "--with-stream",
     "--with-threads",
 ];
+/// Configure switches for the mail proxy modules, used with the `mail` feature.
+const NGX_MAIL_MODULES: [&str; 2] = ["--with-mail", "--with-mail_ssl_module"];
 /// Additional configuration flags to use when building on Linux.
 const NGX_LINUX_ADDITIONAL_OPTS: [&str; 3] = [
     "--with-file-aio",
@@ -112,10 +114,14 @@ fn main() -> Result<(), Box<dyn StdError>> {
 /// Generates Rust bindings for NGINX
 fn generate_binding(nginx_source_dir: PathBuf) {
     let autoconf_makefile_path = nginx_source_dir.join("objs").join("Makefile");
-    let clang_args: Vec<String> = parse_includes_from_makefile(&autoconf_makefile_path)
+    let mut clang_args: Vec<String> = parse_includes_from_makefile(&autoconf_makefile_path)
         .into_iter()
         .map(|path| format!("-I{}", path.to_string_lossy()))
         .collect();
+    // Tell wrapper.h to include the mail headers
+    if env::var("CARGO_FEATURE_MAIL").is_ok() {
+        clang_args.push("-DNGX_RS_MAIL".to_string());
+    }
 
     let bindings = bindgen::Builder::default()
         // Bindings will not compile on Linux without block listing this item
@@ -508,6 +514,11 @@ fn nginx_configure_flags(
         for module in NGX_BASE_MODULES {
             modules.push(module.to_string());
         }
+        if env::var("CARGO_FEATURE_MAIL").is_ok() {
+            for module in NGX_MAIL_MODULES {
+                modules.push(module.to_string());
+            }
+        }
         modules
     };
     let mut nginx_opts = vec![format_source_path("--prefix", nginx_install_dir)];
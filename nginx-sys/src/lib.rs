@@ -204,5 +204,6 @@ pub unsafe fn add_to_ngx_table(
         table.value.len = value.len() as _;
         table.value.data = str_to_uchar(pool, value);
         table.lowcase_key = str_to_uchar(pool, String::from(key).to_ascii_lowercase().as_str());
+        table.next = std::ptr::null_mut();
     })
 }
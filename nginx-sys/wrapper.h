@@ -1,4 +1,5 @@
 #include <ngx_http.h>
+#include <ngx_stream.h>
 #include <ngx_conf_file.h>
 #include <ngx_config.h>
 #include <ngx_core.h>
@@ -8,4 +9,14 @@ const size_t NGX_RS_HTTP_MAIN_CONF_OFFSET = NGX_HTTP_MAIN_CONF_OFFSET;
 const size_t NGX_RS_HTTP_SRV_CONF_OFFSET = NGX_HTTP_SRV_CONF_OFFSET;
 const size_t NGX_RS_HTTP_LOC_CONF_OFFSET = NGX_HTTP_LOC_CONF_OFFSET;
 
+const size_t NGX_RS_STREAM_MAIN_CONF_OFFSET = NGX_STREAM_MAIN_CONF_OFFSET;
+const size_t NGX_RS_STREAM_SRV_CONF_OFFSET = NGX_STREAM_SRV_CONF_OFFSET;
+
+#ifdef NGX_RS_MAIL
+#include <ngx_mail.h>
+
+const size_t NGX_RS_MAIL_MAIN_CONF_OFFSET = NGX_MAIL_MAIN_CONF_OFFSET;
+const size_t NGX_RS_MAIL_SRV_CONF_OFFSET = NGX_MAIL_SRV_CONF_OFFSET;
+#endif
+
 const char *NGX_RS_MODULE_SIGNATURE = NGX_MODULE_SIGNATURE;
@@ -0,0 +1,5 @@
+mod sleep;
+mod spawn;
+
+pub use sleep::*;
+pub use spawn::*;
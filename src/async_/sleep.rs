@@ -0,0 +1,63 @@
+use crate::core::*;
+
+use std::cell::RefCell;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+use std::time::Duration;
+
+/// Wait until `duration` has elapsed, using a [`Timer`].
+///
+/// The timer is cancelable: it does not delay a graceful shutdown of the worker process.
+pub fn sleep(duration: Duration) -> Sleep {
+    Sleep {
+        duration,
+        timer: None,
+        state: Rc::new(RefCell::new(SleepState::default())),
+    }
+}
+
+/// Future returned by [`sleep`].
+pub struct Sleep {
+    duration: Duration,
+    timer: Option<Timer>,
+    state: Rc<RefCell<SleepState>>,
+}
+
+#[derive(Default)]
+struct SleepState {
+    fired: bool,
+    waker: Option<Waker>,
+}
+
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        let this = self.get_mut();
+
+        let mut state = this.state.borrow_mut();
+        if state.fired {
+            return Poll::Ready(());
+        }
+        if !state.waker.as_ref().is_some_and(|waker| waker.will_wake(cx.waker())) {
+            state.waker = Some(cx.waker().clone());
+        }
+        drop(state);
+
+        if this.timer.is_none() {
+            let state = this.state.clone();
+            let mut timer = Timer::new(move || {
+                let mut state = state.borrow_mut();
+                state.fired = true;
+                if let Some(waker) = state.waker.take() {
+                    waker.wake();
+                }
+            });
+            timer.start(this.duration);
+            this.timer = Some(timer);
+        }
+        Poll::Pending
+    }
+}
@@ -0,0 +1,210 @@
+use crate::core::*;
+use crate::ffi::*;
+use crate::http::Request;
+
+use std::cell::{Cell, RefCell, UnsafeCell};
+use std::future::Future;
+use std::os::raw::c_void;
+use std::pin::Pin;
+use std::ptr;
+use std::rc::Rc;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+use std::thread::{self, ThreadId};
+
+type TaskFuture = Pin<Box<dyn Future<Output = Status>>>;
+
+/// Handle to the request of a task spawned with [`spawn`].
+///
+/// The task is dropped before the request is freed, so the request is valid whenever the task
+/// future runs.
+pub struct RequestRef(*mut ngx_http_request_t);
+
+impl RequestRef {
+    /// The request of the task.
+    pub fn get(&mut self) -> &mut Request {
+        // SAFETY: the task owning this handle is dropped when the request pool is destroyed.
+        unsafe { Request::from_ngx_http_request(self.0) }
+    }
+}
+
+struct TaskState {
+    // The event loop thread, the only one allowed to use the task wakers.
+    thread: ThreadId,
+    event: UnsafeCell<ngx_event_t>,
+    // Null once the task is completed or cancelled.
+    request: Cell<*mut ngx_http_request_t>,
+    future: RefCell<Option<TaskFuture>>,
+}
+
+impl TaskState {
+    fn wake(&self) {
+        if self.request.get().is_null() {
+            return;
+        }
+        // SAFETY: the event is initialized in `spawn`, and removed from the queue when the task is
+        // cancelled.
+        unsafe { ngx_post_event(self.event.get(), ptr::addr_of_mut!(ngx_posted_events)) };
+    }
+
+    fn cancel(&self) {
+        self.request.set(ptr::null_mut());
+
+        let ev = self.event.get();
+        unsafe {
+            if (*ev).posted() != 0 {
+                ngx_delete_posted_event(ev);
+            }
+        }
+
+        // The future is being polled if it is borrowed; `task_handler` drops it afterwards.
+        if let Ok(mut future) = self.future.try_borrow_mut() {
+            future.take();
+        }
+    }
+}
+
+/// Owner of a task, allocated from the request pool: the task is cancelled when the pool is
+/// destroyed.
+struct TaskHandle(Rc<TaskState>);
+
+impl Drop for TaskHandle {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+/// Spawn a task running a future for the request.
+///
+/// The future is created by `f` from a [`RequestRef`] and polled from the event loop, every time
+/// its [`Waker`] is woken. The request is kept alive until the future resolves, then it is
+/// finalized with the returned status. The task is cancelled, dropping the future, if the request
+/// is terminated before, e.g. when the client closes the connection.
+///
+/// This must be called from a content handler, which must return the returned status: `NGX_DONE`
+/// once the task is spawned, or `NGX_ERROR`.
+///
+/// The task state is not thread-safe: waking, cloning or dropping its [`Waker`] from a thread other
+/// than the event loop thread aborts the process.
+///
+/// ```rust,ignore
+/// http_request_handler!(delay_handler, |request: &mut http::Request| {
+///     async_::spawn(request, |mut request| async move {
+///         async_::sleep(Duration::from_millis(100)).await;
+///         request.get().discard_request_body();
+///         http::HTTPStatus::NO_CONTENT.into()
+///     })
+/// });
+/// ```
+pub fn spawn<F, Fut>(request: &mut Request, f: F) -> Status
+where
+    F: FnOnce(RequestRef) -> Fut,
+    Fut: Future<Output = Status> + 'static,
+{
+    let r: *mut ngx_http_request_t = request.into();
+
+    let state = Rc::new(TaskState {
+        thread: thread::current().id(),
+        // SAFETY: all-zero is a valid, inactive `ngx_event_t`.
+        event: UnsafeCell::new(unsafe { std::mem::zeroed() }),
+        request: Cell::new(r),
+        future: RefCell::new(Some(Box::pin(f(RequestRef(r))))),
+    });
+
+    if request.pool().allocate(TaskHandle(state.clone())).is_null() {
+        return Status::NGX_ERROR;
+    }
+
+    unsafe {
+        let ev = state.event.get();
+        (*ev).data = Rc::as_ptr(&state) as *mut c_void;
+        (*ev).handler = Some(task_handler);
+        (*ev).log = (*(*r).connection).log;
+
+        // Released by `ngx_http_finalize_request` when the future resolves.
+        let main = (*r).main;
+        (*main).set_count((*main).count() + 1);
+
+        // Terminate the request, and thus cancel the task, if the client closes the connection.
+        if (*r).reading_body() == 0 {
+            (*r).read_event_handler = Some(ngx_http_test_reading);
+        }
+
+        ngx_post_event(ev, ptr::addr_of_mut!(ngx_posted_events));
+    }
+
+    Status::NGX_DONE
+}
+
+unsafe extern "C" fn task_handler(ev: *mut ngx_event_t) {
+    let data = (*ev).data as *const TaskState;
+    // Keep the task alive while it is polled, even if the request pool is destroyed.
+    Rc::increment_strong_count(data);
+    let state = Rc::from_raw(data);
+
+    let r = state.request.get();
+    if r.is_null() {
+        return;
+    }
+
+    let waker = Waker::from_raw(RawWaker::new(Rc::into_raw(state.clone()) as *const (), &VTABLE));
+    let mut cx = Context::from_waker(&waker);
+
+    let poll = match state.future.borrow_mut().as_mut() {
+        Some(future) => future.as_mut().poll(&mut cx),
+        None => return,
+    };
+
+    if state.request.get().is_null() {
+        // Cancelled while polled.
+        state.future.borrow_mut().take();
+        return;
+    }
+
+    if let Poll::Ready(status) = poll {
+        state.cancel();
+
+        let c = (*r).connection;
+        ngx_http_finalize_request(r, status.0);
+        ngx_http_run_posted_requests(c);
+    }
+}
+
+static VTABLE: RawWakerVTable = RawWakerVTable::new(waker_clone, waker_wake, waker_wake_by_ref, waker_drop);
+
+/// Abort unless called from the event loop thread of the task.
+///
+/// A [`Waker`] is `Send` and `Sync`, but the task state is reference counted with [`Rc`] and woken
+/// by posting an event, which are only valid on the event loop thread. Aborting is the only way to
+/// prevent a data race once a waker is used from another thread.
+///
+/// # Safety
+///
+/// `data` must be the task state of a waker, which owns a reference to it. Reading the thread id
+/// from any thread is sound, as it is never modified.
+unsafe fn check_thread(data: *const ()) {
+    if (*(data as *const TaskState)).thread != thread::current().id() {
+        std::process::abort();
+    }
+}
+
+unsafe fn waker_clone(data: *const ()) -> RawWaker {
+    check_thread(data);
+    Rc::increment_strong_count(data as *const TaskState);
+    RawWaker::new(data, &VTABLE)
+}
+
+unsafe fn waker_wake(data: *const ()) {
+    check_thread(data);
+    let state = Rc::from_raw(data as *const TaskState);
+    state.wake();
+}
+
+unsafe fn waker_wake_by_ref(data: *const ()) {
+    check_thread(data);
+    (*(data as *const TaskState)).wake();
+}
+
+unsafe fn waker_drop(data: *const ()) {
+    check_thread(data);
+    drop(Rc::from_raw(data as *const TaskState));
+}
@@ -0,0 +1,336 @@
+use crate::core::buffer::Buffer;
+use crate::core::pool::Pool;
+use crate::ffi::*;
+
+use std::marker::PhantomData;
+use std::ptr;
+
+/// Wrapper struct for a chain of buffers (`ngx_chain_t` links), as passed to output filters.
+///
+/// The links are allocated from a [`Pool`] and are not freed when the `Chain` is dropped. An
+/// empty chain is represented by a null pointer.
+///
+/// See https://nginx.org/en/docs/dev/development_guide.html#buffer for details.
+pub struct Chain {
+    head: *mut ngx_chain_t,
+    tail: *mut ngx_chain_t,
+}
+
+impl Default for Chain {
+    fn default() -> Self {
+        Chain::new()
+    }
+}
+
+impl Chain {
+    /// Creates an empty `Chain`.
+    pub fn new() -> Chain {
+        Chain {
+            head: ptr::null_mut(),
+            tail: ptr::null_mut(),
+        }
+    }
+
+    /// Creates a `Chain` from an `ngx_chain_t` pointer, which may be null.
+    ///
+    /// # Safety
+    /// The caller must ensure that `cl` is null or points to a valid chain of links, each with a
+    /// valid buffer, terminated by a null `next` pointer.
+    pub unsafe fn from_ngx_chain(cl: *mut ngx_chain_t) -> Chain {
+        let mut tail = cl;
+        while !tail.is_null() && !(*tail).next.is_null() {
+            tail = (*tail).next;
+        }
+        Chain { head: cl, tail }
+    }
+
+    /// Returns the first link of the chain as a raw pointer, or null if the chain is empty.
+    pub fn as_ngx_chain(&self) -> *mut ngx_chain_t {
+        self.head
+    }
+
+    /// Returns the first link of the chain, e.g. for [`Request::output_filter`].
+    ///
+    /// [`Request::output_filter`]: crate::http::Request::output_filter
+    pub fn as_ngx_chain_mut(&mut self) -> Option<&mut ngx_chain_t> {
+        unsafe { self.head.as_mut() }
+    }
+
+    /// Returns `true` if the chain has no links.
+    pub fn is_empty(&self) -> bool {
+        self.head.is_null()
+    }
+
+    /// Returns the number of links in the chain.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Appends a buffer to the chain, allocating the link from `pool`.
+    ///
+    /// Returns `None` if the link cannot be allocated.
+    pub fn push<B: Buffer>(&mut self, pool: &mut Pool, mut buf: B) -> Option<()> {
+        let cl = pool.alloc_chain_link();
+        if cl.is_null() {
+            return None;
+        }
+        unsafe {
+            (*cl).buf = buf.as_ngx_buf_mut();
+            (*cl).next = ptr::null_mut();
+            self.append(Chain::from_ngx_chain(cl));
+        }
+        Some(())
+    }
+
+    /// Moves all links of `other` to the end of the chain.
+    pub fn append(&mut self, other: Chain) {
+        if other.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            self.head = other.head;
+        } else {
+            unsafe { (*self.tail).next = other.head };
+        }
+        self.tail = other.tail;
+    }
+
+    /// Splits the chain in two at the given link index.
+    ///
+    /// Returns a chain with the links from `at` onwards; `self` keeps the links before `at`. If
+    /// `at` is greater than or equal to the length, the returned chain is empty.
+    pub fn split_off(&mut self, at: usize) -> Chain {
+        if at == 0 {
+            return std::mem::take(self);
+        }
+        let mut cl = self.head;
+        for _ in 1..at {
+            if cl.is_null() {
+                break;
+            }
+            cl = unsafe { (*cl).next };
+        }
+        if cl.is_null() || unsafe { (*cl).next.is_null() } {
+            return Chain::new();
+        }
+        unsafe {
+            let rest = Chain {
+                head: (*cl).next,
+                tail: self.tail,
+            };
+            (*cl).next = ptr::null_mut();
+            self.tail = cl;
+            rest
+        }
+    }
+
+    /// Marks the end of the output in the last buffer of the chain.
+    ///
+    /// Sets `last_in_chain` on the last buffer and clears it on the others. If `last_buf` is
+    /// `true`, which should only be the case for the main request, `last_buf` is set the same
+    /// way to mark the end of the response.
+    pub fn set_last(&mut self, last_buf: bool) {
+        for cl in self.iter_mut() {
+            let last = cl.next.is_null();
+            let buf = unsafe { &mut *cl.buf };
+            buf.set_last_in_chain(last as _);
+            buf.set_last_buf((last && last_buf) as _);
+        }
+    }
+
+    /// Returns an iterator over the links of the chain.
+    pub fn iter(&self) -> ChainIter<'_> {
+        ChainIter {
+            cl: self.head,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns an iterator over the links of the chain that allows modifying each link.
+    pub fn iter_mut(&mut self) -> ChainIterMut<'_> {
+        ChainIterMut {
+            cl: self.head,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns an iterator over the buffers of the chain.
+    pub fn buffers(&self) -> impl Iterator<Item = &ngx_buf_t> {
+        self.iter().map(|cl| unsafe { &*cl.buf })
+    }
+
+    /// Copies the contents of all buffers into a `Vec`.
+    ///
+    /// Buffers in memory are copied directly; buffers backed by a file, like a request body
+    /// written to a temporary file, are read from the file. Special buffers without data are
+    /// skipped.
+    ///
+    /// Returns `None` if reading a file fails.
+    pub fn to_vec(&self) -> Option<Vec<u8>> {
+        let mut out = Vec::new();
+        for buf in self.buffers() {
+            if buf.temporary() != 0 || buf.memory() != 0 || buf.mmap() != 0 {
+                if !buf.pos.is_null() && buf.last > buf.pos {
+                    let len = buf.last as usize - buf.pos as usize;
+                    out.extend_from_slice(unsafe { std::slice::from_raw_parts(buf.pos, len) });
+                }
+            } else if buf.in_file() != 0 && !buf.file.is_null() && buf.file_last > buf.file_pos {
+                let len = (buf.file_last - buf.file_pos) as usize;
+                let start = out.len();
+                out.resize(start + len, 0);
+                let n = unsafe { ngx_read_file(buf.file, out[start..].as_mut_ptr(), len, buf.file_pos) };
+                if n != len as isize {
+                    return None;
+                }
+            }
+        }
+        Some(out)
+    }
+}
+
+impl From<Chain> for *mut ngx_chain_t {
+    fn from(chain: Chain) -> Self {
+        chain.head
+    }
+}
+
+/// Iterator over the links of a [`Chain`].
+pub struct ChainIter<'a> {
+    cl: *mut ngx_chain_t,
+    _marker: PhantomData<&'a ngx_chain_t>,
+}
+
+impl<'a> Iterator for ChainIter<'a> {
+    type Item = &'a ngx_chain_t;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let cl = unsafe { self.cl.as_ref()? };
+        self.cl = cl.next;
+        Some(cl)
+    }
+}
+
+/// Mutable iterator over the links of a [`Chain`].
+pub struct ChainIterMut<'a> {
+    cl: *mut ngx_chain_t,
+    _marker: PhantomData<&'a mut ngx_chain_t>,
+}
+
+impl<'a> Iterator for ChainIterMut<'a> {
+    type Item = &'a mut ngx_chain_t;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let cl = unsafe { self.cl.as_mut()? };
+        self.cl = cl.next;
+        Some(cl)
+    }
+}
+
+/// Builder for a [`Chain`] of buffers allocated from a [`Pool`].
+///
+/// ```rust,ignore
+/// let mut pool = request.pool();
+/// let body = pool.create_buffer_from_static_str("Hello, world!\n")?;
+/// let mut chain = ChainBuilder::new(&mut pool).buffer(body).last_buf(request.is_main()).build()?;
+/// request.output_filter(chain.as_ngx_chain_mut()?);
+/// ```
+pub struct ChainBuilder<'a> {
+    pool: &'a mut Pool,
+    chain: Option<Chain>,
+    last_buf: Option<bool>,
+}
+
+impl<'a> ChainBuilder<'a> {
+    /// Creates a builder allocating chain links from `pool`.
+    pub fn new(pool: &'a mut Pool) -> ChainBuilder<'a> {
+        ChainBuilder {
+            pool,
+            chain: Some(Chain::new()),
+            last_buf: None,
+        }
+    }
+
+    /// Appends a buffer to the chain.
+    pub fn buffer<B: Buffer>(mut self, buf: B) -> Self {
+        if let Some(chain) = self.chain.as_mut() {
+            if chain.push(self.pool, buf).is_none() {
+                self.chain = None;
+            }
+        }
+        self
+    }
+
+    /// Marks the last buffer of the chain with [`Chain::set_last`] when the chain is built.
+    pub fn last_buf(mut self, last_buf: bool) -> Self {
+        self.last_buf = Some(last_buf);
+        self
+    }
+
+    /// Builds the chain.
+    ///
+    /// Returns `None` if a link allocation failed.
+    pub fn build(self) -> Option<Chain> {
+        let mut chain = self.chain?;
+        if let Some(last_buf) = self.last_buf {
+            chain.set_last(last_buf);
+        }
+        Some(chain)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn links(n: usize) -> (Vec<ngx_buf_t>, Vec<ngx_chain_t>) {
+        let bufs = vec![unsafe { std::mem::zeroed::<ngx_buf_t>() }; n];
+        let links = vec![
+            ngx_chain_t {
+                buf: ptr::null_mut(),
+                next: ptr::null_mut(),
+            };
+            n
+        ];
+        (bufs, links)
+    }
+
+    fn chain(bufs: &mut [ngx_buf_t], links: &mut [ngx_chain_t]) -> Chain {
+        let mut chain = Chain::new();
+        for (buf, cl) in bufs.iter_mut().zip(links.iter_mut()) {
+            cl.buf = buf;
+            chain.append(unsafe { Chain::from_ngx_chain(cl) });
+        }
+        chain
+    }
+
+    #[test]
+    fn test_split_append() {
+        let (mut bufs, mut links) = links(3);
+        let mut c = chain(&mut bufs, &mut links);
+        assert_eq!(c.len(), 3);
+
+        let rest = c.split_off(1);
+        assert_eq!(c.len(), 1);
+        assert_eq!(rest.len(), 2);
+        assert!(c.split_off(5).is_empty());
+
+        c.append(rest);
+        assert_eq!(c.len(), 3);
+        assert_eq!(c.split_off(0).len(), 3);
+        assert!(c.is_empty());
+    }
+
+    #[test]
+    fn test_set_last() {
+        let (mut bufs, mut links) = links(2);
+        let mut c = chain(&mut bufs, &mut links);
+
+        c.set_last(true);
+        let flags: Vec<_> = c.buffers().map(|b| (b.last_in_chain(), b.last_buf())).collect();
+        assert_eq!(flags, [(0, 0), (1, 1)]);
+
+        c.set_last(false);
+        let flags: Vec<_> = c.buffers().map(|b| (b.last_in_chain(), b.last_buf())).collect();
+        assert_eq!(flags, [(0, 0), (1, 0)]);
+    }
+}
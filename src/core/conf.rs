@@ -0,0 +1,53 @@
+/// MergeConfigError - configuration cannot be merged with levels above.
+#[derive(Debug)]
+pub enum MergeConfigError {
+    /// No value provided for configuration argument
+    NoValue,
+}
+
+impl std::error::Error for MergeConfigError {}
+
+impl std::fmt::Display for MergeConfigError {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            MergeConfigError::NoValue => "no value".fmt(fmt),
+        }
+    }
+}
+
+/// The `Merge` trait provides a method for merging configuration down through each level.
+///
+/// A module configuration should implement this trait for setting its configuration throughout
+/// each level.
+pub trait Merge {
+    /// Module merge function.
+    ///
+    /// # Returns
+    /// Result, Ok on success or MergeConfigError on failure.
+    fn merge(&mut self, prev: &Self) -> Result<(), MergeConfigError>;
+}
+
+impl Merge for () {
+    fn merge(&mut self, _prev: &Self) -> Result<(), MergeConfigError> {
+        Ok(())
+    }
+}
+
+/// Merge a configuration value with the value from the previous level, analogous to
+/// `ngx_conf_merge_value`.
+///
+/// An unset value is inherited from `prev`; if both are unset, `default` is used.
+pub fn merge_conf_value<T: Clone>(conf: &mut Option<T>, prev: &Option<T>, default: T) {
+    if conf.is_none() {
+        *conf = Some(prev.clone().unwrap_or(default));
+    }
+}
+
+/// Merge a configuration value without a default with the value from the previous level.
+///
+/// An unset value is inherited from `prev` and stays unset if `prev` is unset too.
+pub fn merge_conf_option<T: Clone>(conf: &mut Option<T>, prev: &Option<T>) {
+    if conf.is_none() {
+        conf.clone_from(prev);
+    }
+}
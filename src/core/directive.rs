@@ -0,0 +1,185 @@
+use crate::core::*;
+use crate::ffi::*;
+
+use std::ffi::CString;
+use std::fmt;
+use std::ops::BitOr;
+use std::os::raw::{c_char, c_void};
+use std::{ptr, slice};
+
+/// Define a single configuration directive for the context and configuration level types of a
+/// module type, e.g. [`http::DirectiveContext`] and [`http::DirectiveConf`].
+///
+/// Used by [`ngx_http_command`](crate::ngx_http_command) and
+/// [`ngx_stream_command`](crate::ngx_stream_command).
+///
+/// [`http::DirectiveContext`]: crate::http::DirectiveContext
+/// [`http::DirectiveConf`]: crate::http::DirectiveConf
+#[doc(hidden)]
+#[macro_export]
+macro_rules! ngx_conf_command {
+    ($context:ty, $level:ty, {
+        name: $directive:literal,
+        context: [$( $ctx:ident ),+ $(,)?],
+        args: [$( $args:ident ),+ $(,)?],
+        conf: $conf:ident,
+        set: $set:expr $(,)?
+    }) => {
+        $crate::ffi::ngx_command_t {
+            name: $crate::ngx_string!($directive),
+            type_: 0 $( | <$context>::$ctx.0 )+ $( | $crate::core::DirectiveArgs::$args.0 )+,
+            set: {
+                unsafe extern "C" fn set(
+                    cf: *mut $crate::ffi::ngx_conf_t,
+                    cmd: *mut $crate::ffi::ngx_command_t,
+                    conf: *mut ::std::os::raw::c_void,
+                ) -> *mut ::std::os::raw::c_char {
+                    $crate::core::directive_set(cf, cmd, conf, $set)
+                }
+                Some(set)
+            },
+            conf: <$level>::$conf.0,
+            offset: 0,
+            post: ::std::ptr::null_mut(),
+        }
+    };
+    ($context:ty, $level:ty, {
+        name: $directive:literal,
+        context: [$( $ctx:ident ),+ $(,)?],
+        args: [$( $args:ident ),+ $(,)?],
+        conf: $conf:ident,
+        slot: $slot:ty => ($ty:ty, $field:ident) $(,)?
+    }) => {
+        $crate::ffi::ngx_command_t {
+            name: $crate::ngx_string!($directive),
+            type_: 0 $( | <$context>::$ctx.0 )+ $( | $crate::core::DirectiveArgs::$args.0 )+,
+            set: Some($crate::core::conf_set_slot::<$slot>),
+            conf: <$level>::$conf.0,
+            offset: {
+                #[allow(dead_code)]
+                fn field(conf: &mut $ty) -> &mut <$slot as $crate::core::ConfSlot>::Field {
+                    &mut conf.$field
+                }
+                ::std::mem::offset_of!($ty, $field)
+            },
+            post: ::std::ptr::null_mut(),
+        }
+    };
+}
+
+/// Number and kind of arguments a directive accepts.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct DirectiveArgs(pub ngx_uint_t);
+
+impl DirectiveArgs {
+    /// No arguments.
+    pub const NOARGS: DirectiveArgs = DirectiveArgs(NGX_CONF_NOARGS as ngx_uint_t);
+    /// Exactly one argument.
+    pub const TAKE1: DirectiveArgs = DirectiveArgs(NGX_CONF_TAKE1 as ngx_uint_t);
+    /// Exactly two arguments.
+    pub const TAKE2: DirectiveArgs = DirectiveArgs(NGX_CONF_TAKE2 as ngx_uint_t);
+    /// Exactly three arguments.
+    pub const TAKE3: DirectiveArgs = DirectiveArgs(NGX_CONF_TAKE3 as ngx_uint_t);
+    /// Exactly four arguments.
+    pub const TAKE4: DirectiveArgs = DirectiveArgs(NGX_CONF_TAKE4 as ngx_uint_t);
+    /// Exactly five arguments.
+    pub const TAKE5: DirectiveArgs = DirectiveArgs(NGX_CONF_TAKE5 as ngx_uint_t);
+    /// Exactly six arguments.
+    pub const TAKE6: DirectiveArgs = DirectiveArgs(NGX_CONF_TAKE6 as ngx_uint_t);
+    /// Exactly seven arguments.
+    pub const TAKE7: DirectiveArgs = DirectiveArgs(NGX_CONF_TAKE7 as ngx_uint_t);
+    /// One or two arguments.
+    pub const TAKE12: DirectiveArgs = DirectiveArgs((NGX_CONF_TAKE1 | NGX_CONF_TAKE2) as ngx_uint_t);
+    /// One or three arguments.
+    pub const TAKE13: DirectiveArgs = DirectiveArgs((NGX_CONF_TAKE1 | NGX_CONF_TAKE3) as ngx_uint_t);
+    /// Two or three arguments.
+    pub const TAKE23: DirectiveArgs = DirectiveArgs((NGX_CONF_TAKE2 | NGX_CONF_TAKE3) as ngx_uint_t);
+    /// One, two or three arguments.
+    pub const TAKE123: DirectiveArgs = DirectiveArgs((NGX_CONF_TAKE1 | NGX_CONF_TAKE2 | NGX_CONF_TAKE3) as ngx_uint_t);
+    /// One to four arguments.
+    pub const TAKE1234: DirectiveArgs =
+        DirectiveArgs((NGX_CONF_TAKE1 | NGX_CONF_TAKE2 | NGX_CONF_TAKE3 | NGX_CONF_TAKE4) as ngx_uint_t);
+    /// A single `on` or `off` argument.
+    pub const FLAG: DirectiveArgs = DirectiveArgs(NGX_CONF_FLAG as ngx_uint_t);
+    /// The directive opens a block.
+    pub const BLOCK: DirectiveArgs = DirectiveArgs(NGX_CONF_BLOCK as ngx_uint_t);
+    /// Any number of arguments, including none.
+    pub const ANY: DirectiveArgs = DirectiveArgs(NGX_CONF_ANY as ngx_uint_t);
+    /// One or more arguments.
+    pub const ONE_MORE: DirectiveArgs = DirectiveArgs(NGX_CONF_1MORE as ngx_uint_t);
+    /// Two or more arguments.
+    pub const TWO_MORE: DirectiveArgs = DirectiveArgs(NGX_CONF_2MORE as ngx_uint_t);
+}
+
+impl BitOr for DirectiveArgs {
+    type Output = DirectiveArgs;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        DirectiveArgs(self.0 | rhs.0)
+    }
+}
+
+/// DirectiveError - configuration directive cannot be applied.
+#[derive(Debug)]
+pub enum DirectiveError {
+    /// Directive is specified more than once at the same level.
+    Duplicate,
+    /// Invalid value provided for a directive argument.
+    InvalidValue(String),
+    /// Any other error, reported verbatim.
+    Message(String),
+}
+
+impl std::error::Error for DirectiveError {}
+
+impl fmt::Display for DirectiveError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DirectiveError::Duplicate => "is duplicate".fmt(fmt),
+            DirectiveError::InvalidValue(value) => write!(fmt, "invalid value \"{}\"", value),
+            DirectiveError::Message(msg) => msg.fmt(fmt),
+        }
+    }
+}
+
+/// Signature of a directive handler used by [`ngx_http_command`](crate::ngx_http_command) and
+/// [`ngx_stream_command`](crate::ngx_stream_command).
+///
+/// The handler receives the configuration being parsed, the module configuration for the level
+/// the directive was declared with, and the directive arguments without the directive name.
+pub type DirectiveHandler<T> = fn(&mut ngx_conf_t, &mut T, &[&NgxStr]) -> Result<(), DirectiveError>;
+
+/// Invoke a directive handler from an `ngx_command_t` setter.
+///
+/// Errors are logged with the configuration file position and reported to nginx as
+/// `NGX_CONF_ERROR`.
+///
+/// # Safety
+///
+/// The caller has provided the valid `ngx_conf_t`, `ngx_command_t` and configuration pointers
+/// passed to the setter by nginx, and `conf` points to a valid `T`.
+pub unsafe fn directive_set<T>(
+    cf: *mut ngx_conf_t,
+    cmd: *mut ngx_command_t,
+    conf: *mut c_void,
+    handler: DirectiveHandler<T>,
+) -> *mut c_char {
+    let values = slice::from_raw_parts((*(*cf).args).elts as *const ngx_str_t, (*(*cf).args).nelts);
+    let args: Vec<&NgxStr> = values.iter().skip(1).map(|v| NgxStr::from_ngx_str(*v)).collect();
+
+    match handler(&mut *cf, &mut *(conf as *mut T), &args) {
+        Ok(_) => ptr::null_mut(),
+        Err(err) => {
+            let name = NgxStr::from_ngx_str((*cmd).name).to_string_lossy();
+            let message = match err {
+                DirectiveError::Duplicate => format!("\"{}\" directive is duplicate", name),
+                DirectiveError::InvalidValue(value) => format!("invalid value \"{}\" in \"{}\" directive", value, name),
+                DirectiveError::Message(msg) => msg,
+            };
+            let fmt = CString::new("%s").unwrap();
+            let c_message = CString::new(message.replace('\0', "")).unwrap();
+            ngx_conf_log_error(NGX_LOG_EMERG as ngx_uint_t, cf, 0, fmt.as_ptr(), c_message.as_ptr());
+            NGX_CONF_ERROR as _
+        }
+    }
+}
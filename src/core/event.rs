@@ -0,0 +1,86 @@
+use crate::ffi::*;
+
+use std::ptr;
+
+/// Add the event to the queue of posted events, unless it is already posted.
+///
+/// Posted events are processed by the event loop after the current I/O events, which is the
+/// Rust counterpart of the `ngx_post_event` macro.
+///
+/// # Safety
+///
+/// `ev` must be a valid event which outlives its presence in the queue, and `q` a valid queue
+/// such as [`ngx_posted_events`].
+pub unsafe fn ngx_post_event(ev: *mut ngx_event_t, q: *mut ngx_queue_t) {
+    if (*ev).posted() != 0 {
+        return;
+    }
+    (*ev).set_posted(1);
+
+    let x = ptr::addr_of_mut!((*ev).queue);
+    (*x).prev = (*q).prev;
+    (*(*x).prev).next = x;
+    (*x).next = q;
+    (*q).prev = x;
+}
+
+/// Remove a posted event from its queue, which is the Rust counterpart of the
+/// `ngx_delete_posted_event` macro.
+///
+/// # Safety
+///
+/// `ev` must be a valid posted event.
+pub unsafe fn ngx_delete_posted_event(ev: *mut ngx_event_t) {
+    (*ev).set_posted(0);
+
+    let x = ptr::addr_of_mut!((*ev).queue);
+    (*(*x).next).prev = (*x).prev;
+    (*(*x).prev).next = (*x).next;
+    (*x).prev = ptr::null_mut();
+    (*x).next = ptr::null_mut();
+}
+
+/// Schedule the event handler to run after `timer` milliseconds, which is the Rust counterpart
+/// of the `ngx_add_timer` macro.
+///
+/// An already set timer is rescheduled, unless the difference is under `NGX_TIMER_LAZY_DELAY`.
+///
+/// # Safety
+///
+/// `ev` must be a valid event with a handler, which outlives the timer. This must only be called
+/// from a worker process event loop.
+pub unsafe fn ngx_add_timer(ev: *mut ngx_event_t, timer: ngx_msec_t) {
+    let key = ngx_current_msec.wrapping_add(timer);
+
+    if (*ev).timer_set() != 0 {
+        let diff = key.wrapping_sub((*ev).timer.key) as ngx_msec_int_t;
+        if diff.unsigned_abs() < NGX_TIMER_LAZY_DELAY as _ {
+            return;
+        }
+        ngx_del_timer(ev);
+    }
+
+    (*ev).timer.key = key;
+    ngx_rbtree_insert(
+        ptr::addr_of_mut!(ngx_event_timer_rbtree),
+        ptr::addr_of_mut!((*ev).timer),
+    );
+    (*ev).set_timer_set(1);
+}
+
+/// Remove the timer of the event, which is the Rust counterpart of the `ngx_del_timer` macro.
+///
+/// # Safety
+///
+/// `ev` must be a valid event with a timer set.
+pub unsafe fn ngx_del_timer(ev: *mut ngx_event_t) {
+    ngx_rbtree_delete(
+        ptr::addr_of_mut!(ngx_event_timer_rbtree),
+        ptr::addr_of_mut!((*ev).timer),
+    );
+
+    (*ev).timer.left = ptr::null_mut();
+    (*ev).timer.right = ptr::null_mut();
+    (*ev).timer.parent = ptr::null_mut();
+    (*ev).set_timer_set(0);
+}
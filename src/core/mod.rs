@@ -1,12 +1,28 @@
 mod buffer;
+mod chain;
+mod conf;
+mod directive;
+mod event;
 mod pool;
+mod shm;
+mod slot;
 mod status;
 mod string;
+mod thread_pool;
+mod timer;
 
 pub use buffer::*;
+pub use chain::*;
+pub use conf::*;
+pub use directive::*;
+pub use event::*;
 pub use pool::*;
+pub use shm::*;
+pub use slot::*;
 pub use status::*;
 pub use string::*;
+pub use thread_pool::*;
+pub use timer::*;
 
 /// Static empty configuration directive initializer for [`ngx_command_t`].
 ///
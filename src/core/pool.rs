@@ -67,6 +67,13 @@ impl Pool {
         Some(MemoryBuffer::from_ngx_buf(buf))
     }
 
+    /// Allocates a chain link from the pool, reusing a free link if available.
+    ///
+    /// Returns a raw pointer to the link with an unset `next` pointer, or null if allocation fails.
+    pub fn alloc_chain_link(&mut self) -> *mut ngx_chain_t {
+        unsafe { ngx_alloc_chain_link(self.0) }
+    }
+
     /// Adds a cleanup handler for a value in the memory pool.
     ///
     /// Returns `Ok(())` if the cleanup handler is successfully added, or `Err(())` if the cleanup handler cannot be added.
@@ -112,6 +119,18 @@ impl Pool {
         self.calloc(mem::size_of::<T>()) as *mut T
     }
 
+    /// Copies a string into the pool, e.g. for a header value or a request URI.
+    ///
+    /// Returns the copy as an `ngx_str_t`, or `None` if allocation fails.
+    pub fn alloc_str(&mut self, s: &[u8]) -> Option<ngx_str_t> {
+        let data = unsafe { ngx_pnalloc(self.0, s.len()) } as *mut u_char;
+        if data.is_null() {
+            return None;
+        }
+        unsafe { ptr::copy_nonoverlapping(s.as_ptr(), data, s.len()) };
+        Some(ngx_str_t { len: s.len(), data })
+    }
+
     /// Allocates memory for a value of a specified type and adds a cleanup handler to the memory pool.
     ///
     /// Returns a typed pointer to the allocated memory if successful, or a null pointer if allocation or cleanup handler addition fails.
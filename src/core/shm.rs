@@ -0,0 +1,443 @@
+use crate::core::*;
+use crate::ffi::*;
+
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::{Deref, DerefMut};
+use std::os::raw::c_void;
+use std::ptr::{self, NonNull};
+
+type ZoneInit<T> = dyn FnOnce(&mut SlabPool, Option<&mut T>) -> Option<T>;
+
+/// Per-cycle state of a [`SharedZone`], allocated from the configuration pool.
+struct ZoneData<T> {
+    init: RefCell<Option<Box<ZoneInit<T>>>>,
+    value: Cell<*mut T>,
+}
+
+/// A named [shared memory] zone holding a value of type `T`, shared by all worker processes.
+///
+/// The zone is registered at configuration time with [`SharedZone::add`], and stored in the
+/// module configuration. The value is allocated from the zone slab pool once the shared memory is
+/// mapped, and accessed at runtime with [`SharedZone::lock`].
+///
+/// As the memory is shared by processes and outlives configuration reloads, `T` must only hold
+/// plain data and pointers to the same zone, e.g. [`SlabBox`]es, but no heap allocations, which is
+/// why [`SharedZone::add`] is unsafe.
+///
+/// ```rust,ignore
+/// #[derive(Default)]
+/// struct Counters {
+///     requests: u64,
+/// }
+///
+/// // in a directive handler
+/// let module = &*addr_of!(ngx_http_counters_module);
+/// // SAFETY: `Counters` is plain data.
+/// conf.zone = unsafe {
+///     SharedZone::<Counters>::add(&mut *cf, "counters", 1024 * 1024, module, |_, prev| {
+///         prev.is_none().then(Counters::default)
+///     })
+/// };
+///
+/// // in a request handler
+/// if let Some(mut counters) = conf.zone.as_ref().and_then(|zone| zone.lock()) {
+///     counters.requests += 1;
+/// }
+/// ```
+///
+/// [shared memory]: https://nginx.org/en/docs/dev/development_guide.html#shared_memory
+pub struct SharedZone<T> {
+    zone: *mut ngx_shm_zone_t,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Clone for SharedZone<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for SharedZone<T> {}
+
+impl<T: 'static> SharedZone<T> {
+    /// Registers the zone `name` of `size` bytes, owned by the module identified by `tag`.
+    ///
+    /// `init` is called once the shared memory is mapped, with the zone slab pool and, on
+    /// configuration reload when the zone is reused, the value of the previous cycle. It returns
+    /// the value to store in the zone, or `None` to keep the previous value; returning `None`
+    /// without a previous value fails the initialization. The previous value is not freed, as
+    /// worker processes of the previous cycle may still use it.
+    ///
+    /// Returns `None` if the zone cannot be added, or is already registered by the module. The
+    /// size must be at least 8 pages; a zero size refers to a zone added with a size elsewhere.
+    ///
+    /// # Safety
+    /// `T` must not own or point to memory outside of the zone, e.g. a [`String`], a [`Box`] or a
+    /// reference to process memory, as it is used by other processes and outlives the cycle.
+    /// Memory of the zone must be allocated from its [`SlabPool`].
+    pub unsafe fn add<F>(cf: &mut ngx_conf_t, name: &str, size: usize, tag: &ngx_module_t, init: F) -> Option<Self>
+    where
+        F: FnOnce(&mut SlabPool, Option<&mut T>) -> Option<T> + 'static,
+    {
+        let mut name = ngx_str_t {
+            len: name.len(),
+            data: name.as_ptr() as *mut u_char,
+        };
+        let tag = tag as *const ngx_module_t as *mut c_void;
+        // SAFETY: `ngx_shared_memory_add` copies the name to the configuration pool.
+        let zone = unsafe { ngx_shared_memory_add(cf, &mut name, size, tag) };
+        if zone.is_null() || unsafe { !(*zone).data.is_null() } {
+            return None;
+        }
+
+        let data = ZoneData::<T> {
+            init: RefCell::new(Some(Box::new(init))),
+            value: Cell::new(ptr::null_mut()),
+        };
+        // SAFETY: the configuration pool lives as long as the cycle of the zone.
+        let data = unsafe { Pool::from_ngx_pool(cf.pool) }.allocate(data);
+        if data.is_null() {
+            return None;
+        }
+
+        unsafe {
+            (*zone).data = data as *mut c_void;
+            (*zone).init = Some(zone_init::<T>);
+        }
+
+        Some(SharedZone {
+            zone,
+            _marker: PhantomData,
+        })
+    }
+
+    /// Name of the zone.
+    pub fn name(&self) -> &NgxStr {
+        unsafe { NgxStr::from_ngx_str((*self.zone).shm.name) }
+    }
+
+    /// Slab pool of the zone, or `None` if the zone is not initialized yet.
+    pub fn slab_pool(&self) -> Option<SlabPool> {
+        let shpool = unsafe { (*self.zone).shm.addr } as *mut ngx_slab_pool_t;
+        if shpool.is_null() || self.value().is_null() {
+            return None;
+        }
+        Some(SlabPool(shpool))
+    }
+
+    /// Locks the zone mutex for exclusive access to the value across worker processes.
+    ///
+    /// Returns `None` if the zone is not initialized yet. The lock must not be taken again by the
+    /// same process before the guard is dropped.
+    pub fn lock(&self) -> Option<SharedZoneGuard<'_, T>> {
+        let pool = self.slab_pool()?;
+        unsafe {
+            ngx_shmtx_lock(ptr::addr_of_mut!((*pool.0).mutex));
+            Some(SharedZoneGuard {
+                pool,
+                value: &mut *self.value(),
+            })
+        }
+    }
+
+    fn value(&self) -> *mut T {
+        let data = unsafe { (*self.zone).data } as *const ZoneData<T>;
+        unsafe { (*data).value.get() }
+    }
+}
+
+/// Value of a zone before its initialization: the value of the previous cycle `data`, or the
+/// value stored in memory which already exists, e.g. in a worker process on Windows.
+unsafe fn previous_value<T>(zone: *mut ngx_shm_zone_t, data: *mut c_void) -> *mut T {
+    let old = data as *const ZoneData<T>;
+    if !old.is_null() {
+        (*old).value.get()
+    } else if (*zone).shm.exists != 0 {
+        (*((*zone).shm.addr as *mut ngx_slab_pool_t)).data as *mut T
+    } else {
+        ptr::null_mut()
+    }
+}
+
+unsafe extern "C" fn zone_init<T>(zone: *mut ngx_shm_zone_t, data: *mut c_void) -> ngx_int_t {
+    let ctx = &*((*zone).data as *const ZoneData<T>);
+    let shpool = (*zone).shm.addr as *mut ngx_slab_pool_t;
+    let mut pool = SlabPool(shpool);
+
+    let mut value = previous_value::<T>(zone, data);
+    let fresh = value.is_null();
+
+    let Some(init) = ctx.init.borrow_mut().take() else {
+        return Status::NGX_ERROR.into();
+    };
+    match init(&mut pool, value.as_mut()) {
+        Some(new) => match pool.allocate(new) {
+            Some(new) => value = new.into_raw(),
+            None => return Status::NGX_ERROR.into(),
+        },
+        None if fresh => return Status::NGX_ERROR.into(),
+        None => {}
+    }
+
+    if fresh {
+        // Identify the zone in slab allocation errors.
+        let log_ctx = format!(
+            " in zone \"{}\"\0",
+            NgxStr::from_ngx_str((*zone).shm.name).to_string_lossy()
+        );
+        let p = pool.alloc(log_ctx.len()) as *mut u_char;
+        if !p.is_null() {
+            ptr::copy_nonoverlapping(log_ctx.as_ptr(), p, log_ctx.len());
+            (*shpool).log_ctx = p;
+        }
+    }
+
+    (*shpool).data = value as *mut c_void;
+    ctx.value.set(value);
+    Status::NGX_OK.into()
+}
+
+/// Exclusive access to the value of a [`SharedZone`], released when dropped.
+pub struct SharedZoneGuard<'a, T> {
+    pool: SlabPool,
+    value: &'a mut T,
+}
+
+impl<T> SharedZoneGuard<'_, T> {
+    /// Slab pool of the zone, which allocates without locking while the guard is held.
+    pub fn slab_pool(&self) -> SlabPool {
+        self.pool
+    }
+}
+
+impl<T> Deref for SharedZoneGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<T> DerefMut for SharedZoneGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.value
+    }
+}
+
+impl<T> Drop for SharedZoneGuard<'_, T> {
+    fn drop(&mut self) {
+        unsafe { ngx_shmtx_unlock(ptr::addr_of_mut!((*self.pool.0).mutex)) };
+    }
+}
+
+/// Wrapper struct for an [`ngx_slab_pool_t`] pointer, the allocator of a shared memory zone.
+///
+/// Allocations lock the zone mutex, unless it is already held by the current process, e.g. with
+/// a [`SharedZoneGuard`].
+///
+/// [`ngx_slab_pool_t`]: https://nginx.org/en/docs/dev/development_guide.html#shared_memory
+#[derive(Clone, Copy)]
+pub struct SlabPool(*mut ngx_slab_pool_t);
+
+impl SlabPool {
+    /// Creates a new `SlabPool` from an `ngx_slab_pool_t` pointer.
+    ///
+    /// # Safety
+    /// The caller must ensure that a valid `ngx_slab_pool_t` pointer is provided, pointing to the
+    /// start of a shared memory zone.
+    pub unsafe fn from_ngx_slab_pool(pool: *mut ngx_slab_pool_t) -> SlabPool {
+        assert!(!pool.is_null());
+        SlabPool(pool)
+    }
+
+    /// Returns the underlying `ngx_slab_pool_t` pointer.
+    pub fn as_ngx_slab_pool(&self) -> *mut ngx_slab_pool_t {
+        self.0
+    }
+
+    fn is_locked(&self) -> bool {
+        // `ngx_shmtx_lock` stores the PID of the holder in the lock.
+        unsafe { *(*self.0).mutex.lock == ngx_pid as ngx_atomic_uint_t }
+    }
+
+    /// Allocates memory from the pool of the specified size.
+    ///
+    /// Returns a raw pointer to the allocated memory, or null if the zone is full.
+    pub fn alloc(&self, size: usize) -> *mut c_void {
+        unsafe {
+            if self.is_locked() {
+                ngx_slab_alloc_locked(self.0, size)
+            } else {
+                ngx_slab_alloc(self.0, size)
+            }
+        }
+    }
+
+    /// Allocates zeroed memory from the pool of the specified size.
+    ///
+    /// Returns a raw pointer to the allocated memory, or null if the zone is full.
+    pub fn calloc(&self, size: usize) -> *mut c_void {
+        unsafe {
+            if self.is_locked() {
+                ngx_slab_calloc_locked(self.0, size)
+            } else {
+                ngx_slab_calloc(self.0, size)
+            }
+        }
+    }
+
+    /// Frees memory allocated from the pool.
+    ///
+    /// # Safety
+    /// `p` must have been allocated from this pool, and must not be used afterwards.
+    pub unsafe fn free(&self, p: *mut c_void) {
+        if self.is_locked() {
+            ngx_slab_free_locked(self.0, p)
+        } else {
+            ngx_slab_free(self.0, p)
+        }
+    }
+
+    /// Moves a value to memory allocated from the pool.
+    ///
+    /// Returns `None` if the zone is full.
+    pub fn allocate<U>(&self, value: U) -> Option<SlabBox<U>> {
+        let p = NonNull::new(self.alloc(mem::size_of::<U>().max(1)) as *mut U)?;
+        unsafe { ptr::write(p.as_ptr(), value) };
+        Some(SlabBox { ptr: p, pool: *self })
+    }
+}
+
+/// A value allocated from a [`SlabPool`], dropped and freed when the box is dropped.
+pub struct SlabBox<U> {
+    ptr: NonNull<U>,
+    pool: SlabPool,
+}
+
+impl<U> SlabBox<U> {
+    /// Consumes the box, returning a pointer to the value which is no longer freed.
+    pub fn into_raw(self) -> *mut U {
+        let p = self.ptr.as_ptr();
+        mem::forget(self);
+        p
+    }
+
+    /// Creates a box from a pointer returned by [`SlabBox::into_raw`].
+    ///
+    /// # Safety
+    /// `p` must have been returned by [`SlabBox::into_raw`] for a box allocated from `pool`.
+    pub unsafe fn from_raw(pool: SlabPool, p: *mut U) -> Self {
+        SlabBox {
+            ptr: NonNull::new_unchecked(p),
+            pool,
+        }
+    }
+}
+
+impl<U> Deref for SlabBox<U> {
+    type Target = U;
+
+    fn deref(&self) -> &U {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl<U> DerefMut for SlabBox<U> {
+    fn deref_mut(&mut self) -> &mut U {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl<U> Drop for SlabBox<U> {
+    fn drop(&mut self) {
+        unsafe {
+            ptr::drop_in_place(self.ptr.as_ptr());
+            self.pool.free(self.ptr.as_ptr() as *mut c_void);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Zone {
+        zone: ngx_shm_zone_t,
+        shpool: ngx_slab_pool_t,
+        data: ZoneData<u64>,
+    }
+
+    impl Zone {
+        fn new(name: &'static str) -> Box<Zone> {
+            let mut zone = Box::new(Zone {
+                zone: unsafe { mem::zeroed() },
+                shpool: unsafe { mem::zeroed() },
+                data: ZoneData {
+                    init: RefCell::new(None),
+                    value: Cell::new(ptr::null_mut()),
+                },
+            });
+            zone.zone.data = ptr::addr_of_mut!(zone.data) as *mut c_void;
+            zone.zone.shm.addr = ptr::addr_of_mut!(zone.shpool) as *mut u_char;
+            zone.zone.shm.name = ngx_str_t {
+                len: name.len(),
+                data: name.as_ptr() as *mut u_char,
+            };
+            zone
+        }
+
+        fn shared(&mut self) -> SharedZone<u64> {
+            SharedZone {
+                zone: &mut self.zone,
+                _marker: PhantomData,
+            }
+        }
+
+        fn previous_value(&mut self, old: Option<&Zone>) -> *mut u64 {
+            let old = old.map_or(ptr::null_mut(), |old| &old.data as *const ZoneData<u64> as *mut c_void);
+            unsafe { previous_value::<u64>(&mut self.zone, old) }
+        }
+    }
+
+    #[test]
+    fn test_name() {
+        let mut zone = Zone::new("counters");
+        assert_eq!(zone.shared().name().to_str(), Ok("counters"));
+    }
+
+    #[test]
+    fn test_slab_pool() {
+        let mut zone = Zone::new("counters");
+        assert!(zone.shared().slab_pool().is_none());
+
+        let mut value = 1u64;
+        zone.data.value.set(&mut value);
+        let pool = zone.shared().slab_pool().map(|pool| pool.as_ngx_slab_pool());
+        assert_eq!(pool, Some(ptr::addr_of_mut!(zone.shpool)));
+
+        zone.zone.shm.addr = ptr::null_mut();
+        assert!(zone.shared().slab_pool().is_none());
+    }
+
+    #[test]
+    fn test_previous_value() {
+        let mut zone = Zone::new("counters");
+        assert!(zone.previous_value(None).is_null());
+
+        // The value of the previous cycle.
+        let mut value = 1u64;
+        let old = Zone::new("counters");
+        old.data.value.set(&mut value);
+        assert_eq!(zone.previous_value(Some(&old)), ptr::addr_of_mut!(value));
+
+        // The value stored in the memory of the zone, which is ignored unless the memory exists.
+        let mut existing = 2u64;
+        zone.shpool.data = ptr::addr_of_mut!(existing) as *mut c_void;
+        assert!(zone.previous_value(None).is_null());
+        zone.zone.shm.exists = 1;
+        assert_eq!(zone.previous_value(None), ptr::addr_of_mut!(existing));
+        assert_eq!(zone.previous_value(Some(&old)), ptr::addr_of_mut!(value));
+    }
+}
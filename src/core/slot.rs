@@ -0,0 +1,194 @@
+use crate::core::*;
+use crate::ffi::*;
+
+use std::marker::PhantomData;
+use std::os::raw::{c_char, c_void};
+use std::time::Duration;
+
+/// The `ConfSlot` trait describes how a directive stores its arguments into a configuration field.
+///
+/// These are the Rust counterparts of the nginx `ngx_conf_set_*_slot` functions. A slot is bound
+/// to a field of a module configuration with the `slot` form of
+/// [`ngx_http_commands`](crate::ngx_http_commands) or
+/// [`ngx_stream_commands`](crate::ngx_stream_commands), which records the field offset in the
+/// command and checks that the field type matches [`ConfSlot::Field`].
+///
+/// Fields use `Option` as the "unset" value, so they can be merged with [`merge_conf_value`] and
+/// [`merge_conf_option`] from a [`Merge`] implementation.
+///
+/// See https://nginx.org/en/docs/dev/development_guide.html#config_directives for details.
+pub trait ConfSlot {
+    /// Type of the configuration field the slot writes to.
+    type Field;
+
+    /// Parse the directive arguments (without the directive name) into the field.
+    fn set(cf: &mut ngx_conf_t, field: &mut Self::Field, args: &[&NgxStr]) -> Result<(), DirectiveError>;
+}
+
+/// Setter for a configuration field described by a [`ConfSlot`].
+///
+/// # Safety
+///
+/// Callers should provide the valid `ngx_conf_t`, `ngx_command_t` and configuration pointers
+/// passed to the setter by nginx, and the command `offset` must point to a `S::Field` within the
+/// configuration.
+pub unsafe extern "C" fn conf_set_slot<S: ConfSlot>(
+    cf: *mut ngx_conf_t,
+    cmd: *mut ngx_command_t,
+    conf: *mut c_void,
+) -> *mut c_char {
+    let field = (conf as *mut u8).add((*cmd).offset) as *mut c_void;
+    directive_set::<S::Field>(cf, cmd, field, S::set)
+}
+
+/// Slot for an `on` | `off` directive, analogous to `ngx_conf_set_flag_slot`.
+pub struct FlagSlot;
+
+impl ConfSlot for FlagSlot {
+    type Field = Option<bool>;
+
+    fn set(cf: &mut ngx_conf_t, field: &mut Self::Field, args: &[&NgxStr]) -> Result<(), DirectiveError> {
+        if field.is_some() {
+            return Err(DirectiveError::Duplicate);
+        }
+
+        let value = args.first().copied().unwrap_or_default();
+        *field = match value.as_bytes() {
+            v if v.eq_ignore_ascii_case(b"on") => Some(true),
+            v if v.eq_ignore_ascii_case(b"off") => Some(false),
+            _ => {
+                // SAFETY: the first argument of the directive being parsed is its name.
+                let name = unsafe { NgxStr::from_ngx_str(*((*cf.args).elts as *const ngx_str_t)) };
+                return Err(DirectiveError::Message(format!(
+                    "invalid value \"{}\" in \"{}\" directive, it must be \"on\" or \"off\"",
+                    value.to_string_lossy(),
+                    name.to_string_lossy()
+                )));
+            }
+        };
+        Ok(())
+    }
+}
+
+/// Slot for a string directive, analogous to `ngx_conf_set_str_slot`.
+pub struct StrSlot;
+
+impl ConfSlot for StrSlot {
+    type Field = Option<String>;
+
+    fn set(_cf: &mut ngx_conf_t, field: &mut Self::Field, args: &[&NgxStr]) -> Result<(), DirectiveError> {
+        if field.is_some() {
+            return Err(DirectiveError::Duplicate);
+        }
+
+        let value = args.first().copied().unwrap_or_default();
+        match value.to_str() {
+            Ok(s) => *field = Some(s.to_string()),
+            Err(_) => return Err(DirectiveError::InvalidValue(value.to_string_lossy().into())),
+        }
+        Ok(())
+    }
+}
+
+/// Slot for a non-negative integer directive, analogous to `ngx_conf_set_num_slot`.
+pub struct NumSlot;
+
+impl ConfSlot for NumSlot {
+    type Field = Option<ngx_int_t>;
+
+    fn set(_cf: &mut ngx_conf_t, field: &mut Self::Field, args: &[&NgxStr]) -> Result<(), DirectiveError> {
+        if field.is_some() {
+            return Err(DirectiveError::Duplicate);
+        }
+
+        let value = args.first().copied().unwrap_or_default();
+        let n = unsafe { ngx_atoi(value.as_bytes().as_ptr() as *mut u_char, value.as_bytes().len()) };
+        if n == NGX_ERROR as ngx_int_t {
+            return Err(DirectiveError::InvalidValue(value.to_string_lossy().into()));
+        }
+        *field = Some(n);
+        Ok(())
+    }
+}
+
+/// Slot for a size directive with optional `k` or `m` suffix, analogous to
+/// `ngx_conf_set_size_slot`.
+pub struct SizeSlot;
+
+impl ConfSlot for SizeSlot {
+    type Field = Option<usize>;
+
+    fn set(_cf: &mut ngx_conf_t, field: &mut Self::Field, args: &[&NgxStr]) -> Result<(), DirectiveError> {
+        if field.is_some() {
+            return Err(DirectiveError::Duplicate);
+        }
+
+        let value = args.first().copied().unwrap_or_default();
+        let mut s = ngx_str_t {
+            len: value.as_bytes().len(),
+            data: value.as_bytes().as_ptr() as *mut u_char,
+        };
+        let n = unsafe { ngx_parse_size(&mut s) };
+        if n == NGX_ERROR as ssize_t {
+            return Err(DirectiveError::InvalidValue(value.to_string_lossy().into()));
+        }
+        *field = Some(n as usize);
+        Ok(())
+    }
+}
+
+/// Slot for a time interval directive such as `30s` or `1m`, analogous to
+/// `ngx_conf_set_msec_slot`.
+pub struct MsecSlot;
+
+impl ConfSlot for MsecSlot {
+    type Field = Option<Duration>;
+
+    fn set(_cf: &mut ngx_conf_t, field: &mut Self::Field, args: &[&NgxStr]) -> Result<(), DirectiveError> {
+        if field.is_some() {
+            return Err(DirectiveError::Duplicate);
+        }
+
+        let value = args.first().copied().unwrap_or_default();
+        let mut s = ngx_str_t {
+            len: value.as_bytes().len(),
+            data: value.as_bytes().as_ptr() as *mut u_char,
+        };
+        let n = unsafe { ngx_parse_time(&mut s, 0) };
+        if n == NGX_ERROR as ngx_int_t {
+            return Err(DirectiveError::InvalidValue(value.to_string_lossy().into()));
+        }
+        *field = Some(Duration::from_millis(n as u64));
+        Ok(())
+    }
+}
+
+/// The `ConfEnum` trait maps directive values to a Rust type for use with [`EnumSlot`].
+pub trait ConfEnum: Copy + 'static {
+    /// Accepted values and the corresponding variants. Values are matched case-insensitively.
+    const VALUES: &'static [(&'static str, Self)];
+}
+
+/// Slot for a directive taking one of a fixed set of values, analogous to
+/// `ngx_conf_set_enum_slot`.
+pub struct EnumSlot<T>(PhantomData<T>);
+
+impl<T: ConfEnum> ConfSlot for EnumSlot<T> {
+    type Field = Option<T>;
+
+    fn set(_cf: &mut ngx_conf_t, field: &mut Self::Field, args: &[&NgxStr]) -> Result<(), DirectiveError> {
+        if field.is_some() {
+            return Err(DirectiveError::Duplicate);
+        }
+
+        let value = args.first().copied().unwrap_or_default();
+        match T::VALUES
+            .iter()
+            .find(|(name, _)| name.as_bytes().eq_ignore_ascii_case(value.as_bytes()))
+        {
+            Some((_, v)) => *field = Some(*v),
+            None => return Err(DirectiveError::InvalidValue(value.to_string_lossy().into())),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+use crate::core::*;
+use crate::ffi::*;
+
+use std::os::raw::c_void;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr::{self, NonNull};
+use std::thread;
+
+/// Wrapper struct for an `ngx_thread_pool_t` pointer, a [thread pool] running blocking work
+/// outside of the event loop.
+///
+/// Thread pools are configured with the `thread_pool` directive; the `default` pool exists
+/// unless configured otherwise. NGINX must be built with `--with-threads`.
+///
+/// [thread pool]: https://nginx.org/en/docs/dev/development_guide.html#threads
+#[derive(Clone, Copy)]
+pub struct ThreadPool(NonNull<ngx_thread_pool_t>);
+
+impl ThreadPool {
+    /// Reference the thread pool `name` at configuration time.
+    ///
+    /// NGINX checks that the pool is defined once the configuration is read, and fails to start
+    /// otherwise. Returns `None` if memory allocation fails.
+    pub fn add(cf: &mut ngx_conf_t, name: &str) -> Option<ThreadPool> {
+        // SAFETY: `ngx_thread_pool_add` keeps a pointer to the name, which is thus allocated from
+        // the configuration pool.
+        unsafe {
+            let data = Pool::from_ngx_pool(cf.pool).alloc(name.len()) as *mut u_char;
+            if data.is_null() {
+                return None;
+            }
+            ptr::copy_nonoverlapping(name.as_ptr(), data, name.len());
+            let mut name = ngx_str_t { len: name.len(), data };
+            NonNull::new(ngx_thread_pool_add(cf, &mut name)).map(ThreadPool)
+        }
+    }
+
+    /// Get the thread pool `name` at runtime.
+    ///
+    /// Returns `None` if the pool is not defined.
+    pub fn get(name: &str) -> Option<ThreadPool> {
+        let mut name = ngx_str_t {
+            len: name.len(),
+            data: name.as_ptr() as *mut u_char,
+        };
+        unsafe { NonNull::new(ngx_thread_pool_get(ngx_cycle as *mut _, &mut name)).map(ThreadPool) }
+    }
+
+    /// Returns the underlying `ngx_thread_pool_t` pointer.
+    pub fn as_ngx_thread_pool(&self) -> *mut ngx_thread_pool_t {
+        self.0.as_ptr()
+    }
+
+    /// Run `work` on a thread of the pool, then `complete` with its result on the event loop.
+    ///
+    /// `complete` receives an error if `work` panicked. Returns `NGX_ERROR` if the task cannot be
+    /// queued, in which case neither closure is called.
+    pub fn post<F, R, C>(&self, log: *mut ngx_log_t, work: F, complete: C) -> Status
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+        C: FnOnce(thread::Result<R>) + 'static,
+    {
+        let task = Box::new(BlockingTask::<F, R, C> {
+            // SAFETY: all-zero is a valid, inactive `ngx_thread_task_t`.
+            task: unsafe { std::mem::zeroed() },
+            work: Some(work),
+            result: None,
+            complete: Some(complete),
+        });
+        let task = Box::into_raw(task);
+
+        unsafe {
+            (*task).task.ctx = task as *mut c_void;
+            (*task).task.handler = Some(blocking_task_handler::<F, R, C>);
+            (*task).task.event.data = task as *mut c_void;
+            (*task).task.event.handler = Some(blocking_task_event_handler::<F, R, C>);
+            (*task).task.event.log = log;
+
+            if ngx_thread_task_post(self.0.as_ptr(), &mut (*task).task) != Status::NGX_OK.into() {
+                drop(Box::from_raw(task));
+                return Status::NGX_ERROR;
+            }
+        }
+        Status::NGX_OK
+    }
+}
+
+/// A thread task, owned by the thread pool while queued or running.
+#[repr(C)]
+struct BlockingTask<F, R, C> {
+    task: ngx_thread_task_t,
+    // Only accessed by the thread running the task while queued.
+    work: Option<F>,
+    result: Option<thread::Result<R>>,
+    complete: Option<C>,
+}
+
+unsafe extern "C" fn blocking_task_handler<F, R, C>(data: *mut c_void, _log: *mut ngx_log_t)
+where
+    F: FnOnce() -> R,
+{
+    let task = &mut *(data as *mut BlockingTask<F, R, C>);
+    if let Some(work) = task.work.take() {
+        task.result = Some(panic::catch_unwind(AssertUnwindSafe(work)));
+    }
+}
+
+unsafe extern "C" fn blocking_task_event_handler<F, R, C>(ev: *mut ngx_event_t)
+where
+    C: FnOnce(thread::Result<R>),
+{
+    // NGINX does not use the task after calling the event handler.
+    let mut task = Box::from_raw((*ev).data as *mut BlockingTask<F, R, C>);
+    if let (Some(complete), Some(result)) = (task.complete.take(), task.result.take()) {
+        complete(result);
+    }
+}
@@ -0,0 +1,128 @@
+use crate::core::*;
+use crate::ffi::*;
+
+use std::cell::{Cell, RefCell, UnsafeCell};
+use std::os::raw::c_void;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// A timer calling a Rust closure from the event loop, once or periodically.
+///
+/// The timer is marked `cancelable` by default, so a pending timer does not delay a graceful
+/// shutdown of the worker process, see [`Timer::set_cancelable`]. It is cancelled when dropped; a
+/// timer allocated with [`Pool::allocate`] is thus cancelled when the pool, e.g. the request pool,
+/// is destroyed.
+///
+/// ```rust,ignore
+/// let timer = request.pool().allocate(Timer::new(move || {
+///     ngx_log_debug!(log, "timer fired");
+/// }));
+/// if timer.is_null() {
+///     return Status::NGX_ERROR;
+/// }
+/// unsafe { (*timer).start(Duration::from_secs(1)) };
+/// ```
+///
+/// Timers must only be used from a worker process event loop.
+pub struct Timer {
+    inner: Rc<TimerInner>,
+}
+
+struct TimerInner {
+    event: UnsafeCell<ngx_event_t>,
+    interval: Cell<Option<ngx_msec_t>>,
+    callback: RefCell<Box<dyn FnMut()>>,
+}
+
+impl Timer {
+    /// Creates a stopped timer calling `callback` when it expires.
+    pub fn new<F: FnMut() + 'static>(callback: F) -> Timer {
+        let inner = Rc::new(TimerInner {
+            // SAFETY: all-zero is a valid, inactive `ngx_event_t`.
+            event: UnsafeCell::new(unsafe { std::mem::zeroed() }),
+            interval: Cell::new(None),
+            callback: RefCell::new(Box::new(callback)),
+        });
+
+        unsafe {
+            let ev = inner.event.get();
+            (*ev).data = Rc::as_ptr(&inner) as *mut c_void;
+            (*ev).handler = Some(timer_handler);
+            (*ev).log = (*ngx_cycle).log;
+            (*ev).set_cancelable(1);
+        }
+
+        Timer { inner }
+    }
+
+    /// Sets the log used by NGINX for the timer event, e.g. the request connection log.
+    pub fn set_log(&mut self, log: *mut ngx_log_t) {
+        unsafe { (*self.inner.event.get()).log = log };
+    }
+
+    /// Sets whether the timer is `cancelable`, i.e. discarded by a graceful shutdown of the
+    /// worker process. A timer that completes pending work, e.g. a request, should not be.
+    pub fn set_cancelable(&mut self, cancelable: bool) {
+        unsafe { (*self.inner.event.get()).set_cancelable(cancelable.into()) };
+    }
+
+    /// Starts the timer to expire once after `delay`, rescheduling it if already active.
+    pub fn start(&mut self, delay: Duration) {
+        self.inner.interval.set(None);
+        unsafe { ngx_add_timer(self.inner.event.get(), duration_to_msec(delay)) };
+    }
+
+    /// Starts the timer to expire every `interval`, rescheduling it if already active.
+    pub fn start_periodic(&mut self, interval: Duration) {
+        let msec = duration_to_msec(interval);
+        self.inner.interval.set(Some(msec));
+        unsafe { ngx_add_timer(self.inner.event.get(), msec) };
+    }
+
+    /// Stops the timer if active.
+    pub fn cancel(&mut self) {
+        self.inner.interval.set(None);
+        let ev = self.inner.event.get();
+        unsafe {
+            if (*ev).timer_set() != 0 {
+                ngx_del_timer(ev);
+            }
+        }
+    }
+
+    /// Returns `true` if the timer is scheduled to expire.
+    pub fn is_active(&self) -> bool {
+        unsafe { (*self.inner.event.get()).timer_set() != 0 }
+    }
+}
+
+impl Drop for Timer {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+/// Convert a duration to milliseconds for a timer or a timeout setting.
+///
+/// The value is clamped to `ngx_msec_int_t::MAX`, as nginx compares timer keys as signed
+/// differences and a larger value would expire immediately.
+pub(crate) fn duration_to_msec(duration: Duration) -> ngx_msec_t {
+    duration.as_millis().min(ngx_msec_int_t::MAX as u128) as ngx_msec_t
+}
+
+unsafe extern "C" fn timer_handler(ev: *mut ngx_event_t) {
+    let data = (*ev).data as *const TimerInner;
+    // Keep the closure alive while it runs, even if it drops the timer.
+    Rc::increment_strong_count(data);
+    let inner = Rc::from_raw(data);
+
+    if let Some(interval) = inner.interval.get() {
+        ngx_add_timer(ev, interval);
+    }
+
+    // Not borrowed unless the closure is running, i.e. the event loop is run recursively.
+    let callback = inner.callback.try_borrow_mut();
+    if let Ok(mut callback) = callback {
+        callback();
+    }
+}
@@ -0,0 +1,107 @@
+use crate::core::*;
+use crate::ffi::*;
+use crate::http::Request;
+use crate::ngx_null_string;
+
+use std::borrow::Cow;
+
+impl Request {
+    /// Query string of the request URI, without the `?`, as in the `$args` variable.
+    pub fn args(&self) -> &NgxStr {
+        let r: *const ngx_http_request_t = self.into();
+        unsafe { NgxStr::from_ngx_str((*r).args) }
+    }
+
+    /// Value of the first query string argument `name`, as in the `$arg_name` variable.
+    ///
+    /// The name is matched case-insensitively, and the value is not unescaped.
+    pub fn arg(&self, name: &str) -> Option<&NgxStr> {
+        let r: *const ngx_http_request_t = self.into();
+        let mut value = ngx_null_string!();
+        // SAFETY: `ngx_http_arg` does not modify the request or the name, and sets the value to
+        // part of `r->args`.
+        let rc = unsafe { ngx_http_arg(r as *mut _, name.as_ptr() as *mut u_char, name.len(), &mut value) };
+        if rc != Status::NGX_OK.into() {
+            return None;
+        }
+        Some(unsafe { NgxStr::from_ngx_str(value) })
+    }
+
+    /// Iterate over the unescaped `(name, value)` pairs of the query string.
+    ///
+    /// As for nginx, `%XX` escapes are decoded but `+` is kept as is. An argument without `=` has
+    /// an empty value.
+    pub fn args_iterator(&self) -> ArgsIterator<'_> {
+        ArgsIterator {
+            args: self.args().as_bytes(),
+        }
+    }
+
+    /// Replace the query string of the request URI, e.g. before an upstream request.
+    ///
+    /// Returns `None` if memory allocation fails.
+    pub fn set_args(&mut self, args: &str) -> Option<()> {
+        let args = self.pool().alloc_str(args.as_bytes())?;
+        let r: *mut ngx_http_request_t = self.into();
+        unsafe {
+            (*r).args = args;
+            // The unparsed URI has the previous arguments, and is no longer used for proxying.
+            (*r).set_valid_unparsed_uri(0);
+        }
+        Some(())
+    }
+}
+
+/// Iterator over the unescaped `(name, value)` pairs of a query string.
+///
+/// Returned by [`Request::args_iterator`].
+pub struct ArgsIterator<'a> {
+    args: &'a [u8],
+}
+
+impl<'a> Iterator for ArgsIterator<'a> {
+    type Item = (Cow<'a, [u8]>, Cow<'a, [u8]>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.args.is_empty() {
+                return None;
+            }
+
+            let (arg, rest) = match self.args.iter().position(|&b| b == b'&') {
+                Some(i) => (&self.args[..i], &self.args[i + 1..]),
+                None => (self.args, &[][..]),
+            };
+            self.args = rest;
+
+            if arg.is_empty() {
+                continue;
+            }
+            let (name, value) = match arg.iter().position(|&b| b == b'=') {
+                Some(i) => (&arg[..i], &arg[i + 1..]),
+                None => (arg, &[][..]),
+            };
+            return Some((unescape(name), unescape(value)));
+        }
+    }
+}
+
+/// Decode `%XX` escapes with `ngx_unescape_uri`, borrowing the input if there are none.
+fn unescape(s: &[u8]) -> Cow<'_, [u8]> {
+    if !s.contains(&b'%') {
+        return Cow::Borrowed(s);
+    }
+
+    let mut buf = s.to_vec();
+    let start = buf.as_mut_ptr();
+    let mut src = start;
+    let mut dst = start;
+    // SAFETY: the unescaped string is not longer than the input, and unescaping in place is
+    // supported, as done by nginx for `$arg_` values.
+    let len = unsafe {
+        ngx_unescape_uri(&mut dst, &mut src, buf.len(), 0);
+        dst.offset_from(start) as usize
+    };
+    buf.truncate(len);
+    Cow::Owned(buf)
+}
@@ -0,0 +1,231 @@
+use crate::core::*;
+use crate::ffi::*;
+use crate::http::Request;
+
+/// Request body handler for [`Request::read_body`], as defined with
+/// [`http_request_body_handler`](crate::http_request_body_handler).
+#[derive(Clone, Copy)]
+pub struct RequestBodyHandler(unsafe extern "C" fn(r: *mut ngx_http_request_t));
+
+impl RequestBodyHandler {
+    /// Wrap a handler generated by [`http_request_body_handler`](crate::http_request_body_handler).
+    ///
+    /// # Safety
+    /// `handler` must finalize the request once the whole body is read.
+    #[doc(hidden)]
+    pub const unsafe fn new(handler: unsafe extern "C" fn(r: *mut ngx_http_request_t)) -> Self {
+        RequestBodyHandler(handler)
+    }
+}
+
+/// Request body handler for [`Request::read_body_unbuffered`], as defined with
+/// [`http_request_body_chunk_handler`](crate::http_request_body_chunk_handler).
+#[derive(Clone, Copy)]
+pub struct RequestBodyChunkHandler(unsafe extern "C" fn(r: *mut ngx_http_request_t));
+
+impl RequestBodyChunkHandler {
+    /// Wrap a handler generated by
+    /// [`http_request_body_chunk_handler`](crate::http_request_body_chunk_handler).
+    ///
+    /// # Safety
+    /// `handler` must set the request read event handler to read the rest of the body, and
+    /// finalize the request once the last part is processed.
+    #[doc(hidden)]
+    pub const unsafe fn new(handler: unsafe extern "C" fn(r: *mut ngx_http_request_t)) -> Self {
+        RequestBodyChunkHandler(handler)
+    }
+}
+
+/// Define a static request body handler for [`Request::read_body`].
+///
+/// The handler takes a single [`Request`] argument and returns a [`Status`] once the whole body
+/// is read and available with [`Request::request_body`]. The request is then finalized with the
+/// returned status, which must not be done by the handler itself.
+///
+/// ```rust,ignore
+/// http_request_body_handler!(echo_body_handler, |request: &mut http::Request| {
+///     let Some(body) = request.request_body().and_then(|body| body.to_vec()) else {
+///         return http::HTTPStatus::INTERNAL_SERVER_ERROR.into();
+///     };
+///     // ...
+///     Status::NGX_OK
+/// });
+///
+/// http_request_handler!(echo_handler, |request: &mut http::Request| {
+///     request.read_body(echo_body_handler)
+/// });
+/// ```
+#[macro_export]
+macro_rules! http_request_body_handler {
+    ( $name: ident, $handler: expr ) => {
+        #[allow(non_upper_case_globals)]
+        const $name: $crate::http::RequestBodyHandler = {
+            unsafe extern "C" fn handler(r: *mut $crate::ffi::ngx_http_request_t) {
+                let status: $crate::core::Status = $handler($crate::http::Request::from_ngx_http_request(r));
+                $crate::ffi::ngx_http_finalize_request(r, status.0);
+            }
+
+            unsafe { $crate::http::RequestBodyHandler::new(handler) }
+        };
+    };
+}
+
+/// Define a static request body handler for [`Request::read_body_unbuffered`].
+///
+/// The handler takes the [`Request`], a [`Chain`](crate::core::Chain) with the next part of the
+/// body, and a `bool` which is `true` for the last part, and returns a [`Status`]. The buffers of
+/// the chain are marked as consumed after the handler returns, so the data must be copied if
+/// needed later.
+///
+/// Returning anything but `NGX_OK` before the last part, or any status for the last part,
+/// finalizes the request with that status; the handler must not finalize the request itself.
+///
+/// ```rust,ignore
+/// http_request_body_chunk_handler!(count_handler, |request: &mut http::Request, chunk: &mut Chain, last: bool| {
+///     let Some(data) = chunk.to_vec() else {
+///         return http::HTTPStatus::INTERNAL_SERVER_ERROR.into();
+///     };
+///     // process data ...
+///     if !last {
+///         return Status::NGX_OK;
+///     }
+///     // send the response ...
+/// });
+/// ```
+#[macro_export]
+macro_rules! http_request_body_chunk_handler {
+    ( $name: ident, $handler: expr ) => {
+        #[allow(non_upper_case_globals)]
+        const $name: $crate::http::RequestBodyChunkHandler = {
+            // Returns `true` if a part was processed and more of the body is to be read.
+            unsafe fn process(r: *mut $crate::ffi::ngx_http_request_t) -> bool {
+                let request = $crate::http::Request::from_ngx_http_request(r);
+                let last = !request.is_reading_body();
+                let mut chunk = request.take_request_body_chunk();
+                if chunk.is_empty() && !last {
+                    return false;
+                }
+                let status: $crate::core::Status = $handler(request, &mut chunk, last);
+                $crate::http::consume_request_body_chunk(&mut chunk);
+                if last || status != $crate::core::Status::NGX_OK {
+                    $crate::ffi::ngx_http_finalize_request(r, status.0);
+                    return false;
+                }
+                true
+            }
+
+            unsafe extern "C" fn read_handler(r: *mut $crate::ffi::ngx_http_request_t) {
+                // The read may have stopped with the body buffers full rather than with no more
+                // data available, so read again as long as parts are consumed, as no new read
+                // event may come for the data already received.
+                loop {
+                    let rc = $crate::ffi::ngx_http_read_unbuffered_request_body(r);
+                    if rc >= $crate::ffi::NGX_HTTP_SPECIAL_RESPONSE as $crate::ffi::ngx_int_t {
+                        $crate::ffi::ngx_http_finalize_request(r, rc);
+                        return;
+                    }
+                    if !process(r) {
+                        return;
+                    }
+                }
+            }
+
+            unsafe extern "C" fn handler(r: *mut $crate::ffi::ngx_http_request_t) {
+                (*r).read_event_handler = Some(read_handler);
+                if process(r) {
+                    read_handler(r);
+                }
+            }
+
+            unsafe { $crate::http::RequestBodyChunkHandler::new(handler) }
+        };
+    };
+}
+
+impl Request {
+    /// Read the [request body] and call `handler` when it is complete.
+    ///
+    /// The body is kept in memory or written to a temporary file, depending on the
+    /// `client_body_buffer_size` and `client_body_in_file_only` directives; see
+    /// [`Request::request_body`].
+    ///
+    /// This must be called from a content handler, which must return the returned status as is:
+    /// it is either an error status, or `NGX_DONE` when the request is finalized by `handler`.
+    /// NGINX releases the request reference taken for the body only when a content handler
+    /// returns `NGX_DONE`, so the request would never be freed if called from another phase.
+    ///
+    /// [request body]: https://nginx.org/en/docs/dev/development_guide.html#http_request_body
+    pub fn read_body(&mut self, handler: RequestBodyHandler) -> Status {
+        read_client_body(self, handler.0)
+    }
+
+    /// Read the [request body] without buffering, calling `handler` as each part is received.
+    ///
+    /// Unlike [`Request::read_body`], the body is never written to a temporary file and `handler`
+    /// is called once the reading starts, then every time more data is available, until the last
+    /// part. As for [`Request::read_body`], this must be called from a content handler, which
+    /// must return the returned status as is.
+    ///
+    /// [request body]: https://nginx.org/en/docs/dev/development_guide.html#http_request_body
+    pub fn read_body_unbuffered(&mut self, handler: RequestBodyChunkHandler) -> Status {
+        let r: *mut ngx_http_request_t = self.into();
+        unsafe { (*r).set_request_body_no_buffering(1) };
+        read_client_body(self, handler.0)
+    }
+
+    /// The request body read with [`Request::read_body`].
+    ///
+    /// Returns `None` if the body was not read. Parts of the body may be in memory or in a
+    /// temporary file; use [`Chain::to_vec`] to get the contents regardless.
+    pub fn request_body(&self) -> Option<Chain> {
+        let r: *const ngx_http_request_t = self.into();
+        unsafe {
+            let rb = (*r).request_body;
+            if rb.is_null() {
+                return None;
+            }
+            Some(Chain::from_ngx_chain((*rb).bufs))
+        }
+    }
+
+    /// Is the request body still being read?
+    pub fn is_reading_body(&self) -> bool {
+        let r: *const ngx_http_request_t = self.into();
+        unsafe { (*r).reading_body() != 0 }
+    }
+
+    /// Take the part of the body received since the last call, when reading without buffering.
+    #[doc(hidden)]
+    pub fn take_request_body_chunk(&mut self) -> Chain {
+        let r: *mut ngx_http_request_t = self.into();
+        unsafe {
+            let rb = (*r).request_body;
+            if rb.is_null() {
+                return Chain::new();
+            }
+            let chunk = Chain::from_ngx_chain((*rb).bufs);
+            (*rb).bufs = std::ptr::null_mut();
+            chunk
+        }
+    }
+}
+
+fn read_client_body(request: &mut Request, handler: unsafe extern "C" fn(r: *mut ngx_http_request_t)) -> Status {
+    // SAFETY: `ngx_http_read_client_request_body` increments the main request reference count,
+    // which is released when `handler` finalizes the request.
+    let rc = unsafe { ngx_http_read_client_request_body(request.into(), Some(handler)) };
+    if rc >= NGX_HTTP_SPECIAL_RESPONSE as ngx_int_t {
+        return Status(rc);
+    }
+    Status::NGX_DONE
+}
+
+/// Mark the buffers of a body part as consumed, so NGINX can reuse them for the next part.
+#[doc(hidden)]
+pub fn consume_request_body_chunk(chunk: &mut Chain) {
+    for cl in chunk.iter_mut() {
+        let buf = unsafe { &mut *cl.buf };
+        buf.pos = buf.last;
+        buf.file_pos = buf.file_last;
+    }
+}
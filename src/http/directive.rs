@@ -0,0 +1,121 @@
+use crate::ffi::*;
+
+use std::ops::BitOr;
+
+/// Define a null-terminated array of HTTP configuration directives.
+///
+/// Each entry is a directive as accepted by [`ngx_http_command`]. The terminating null directive
+/// is added automatically.
+///
+/// ```rust,ignore
+/// ngx_http_commands! {
+///     #[no_mangle]
+///     static mut ngx_http_curl_commands = [
+///         {
+///             name: "curl",
+///             context: [LOC],
+///             args: [FLAG],
+///             conf: LOC,
+///             set: |_cf, conf: &mut ModuleConfig, args| {
+///                 conf.enable = args[0].as_bytes().eq_ignore_ascii_case(b"on");
+///                 Ok(())
+///             },
+///         },
+///         {
+///             name: "curl_timeout",
+///             context: [LOC],
+///             args: [TAKE1],
+///             conf: LOC,
+///             slot: MsecSlot => (ModuleConfig, timeout),
+///         },
+///     ];
+/// }
+/// ```
+#[macro_export]
+macro_rules! ngx_http_commands {
+    (
+        $(#[$attr:meta])*
+        $vis:vis static mut $name:ident = [
+            $( { $($directive:tt)* } ),+ $(,)?
+        ];
+    ) => {
+        $(#[$attr])*
+        $vis static mut $name: [$crate::ffi::ngx_command_t; $crate::count!($( { $($directive)* }, )+) + 1] = [
+            $( $crate::ngx_http_command!({ $($directive)* }), )+
+            $crate::ffi::ngx_command_t {
+                name: $crate::ngx_null_string!(),
+                type_: 0,
+                set: None,
+                conf: 0,
+                offset: 0,
+                post: ::std::ptr::null_mut(),
+            },
+        ];
+    };
+}
+
+/// Define a single HTTP configuration directive ([`ngx_command_t`]).
+///
+/// A directive declares its name, the configuration blocks it is allowed in
+/// ([`DirectiveContext`]), its arity ([`DirectiveArgs`](crate::core::DirectiveArgs)) and the
+/// configuration level it stores its value in ([`DirectiveConf`]). The value is then applied
+/// either by:
+///
+/// * `set` - a [`DirectiveHandler`](crate::core::DirectiveHandler) receiving the directive
+///   arguments (without the directive name) and the module configuration for that level. The
+///   configuration type is inferred from the handler and must match the type created by the
+///   module for that level.
+/// * `slot` - a [`ConfSlot`](crate::core::ConfSlot) storing the arguments into a field of the
+///   module configuration. The field type is checked against the slot at compile time.
+///
+/// The handler is wrapped in an `extern "C"` setter, so no hand-written setter is needed.
+///
+/// [`ngx_command_t`]: https://nginx.org/en/docs/dev/development_guide.html#config_directives
+#[macro_export]
+macro_rules! ngx_http_command {
+    ({ $($directive:tt)* }) => {
+        $crate::ngx_conf_command!($crate::http::DirectiveContext, $crate::http::DirectiveConf, { $($directive)* })
+    };
+}
+
+/// Configuration blocks a directive may appear in.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct DirectiveContext(pub ngx_uint_t);
+
+impl DirectiveContext {
+    /// The `http` block.
+    pub const MAIN: DirectiveContext = DirectiveContext(NGX_HTTP_MAIN_CONF as ngx_uint_t);
+    /// A `server` block within the `http` block.
+    pub const SRV: DirectiveContext = DirectiveContext(NGX_HTTP_SRV_CONF as ngx_uint_t);
+    /// A `location` block within the `http` block.
+    pub const LOC: DirectiveContext = DirectiveContext(NGX_HTTP_LOC_CONF as ngx_uint_t);
+    /// An `upstream` block within the `http` block.
+    pub const UPS: DirectiveContext = DirectiveContext(NGX_HTTP_UPS_CONF as ngx_uint_t);
+    /// An `if` block within a `server` block.
+    pub const SIF: DirectiveContext = DirectiveContext(NGX_HTTP_SIF_CONF as ngx_uint_t);
+    /// An `if` block within a `location` block.
+    pub const LIF: DirectiveContext = DirectiveContext(NGX_HTTP_LIF_CONF as ngx_uint_t);
+    /// A `limit_except` block within a `location` block.
+    pub const LMT: DirectiveContext = DirectiveContext(NGX_HTTP_LMT_CONF as ngx_uint_t);
+}
+
+impl BitOr for DirectiveContext {
+    type Output = DirectiveContext;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        DirectiveContext(self.0 | rhs.0)
+    }
+}
+
+/// Configuration level a directive stores its value in.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct DirectiveConf(pub ngx_uint_t);
+
+impl DirectiveConf {
+    /// The module main configuration (`HTTPModule::MainConf`).
+    pub const MAIN: DirectiveConf = DirectiveConf(NGX_RS_HTTP_MAIN_CONF_OFFSET);
+    /// The module server configuration (`HTTPModule::SrvConf`).
+    pub const SRV: DirectiveConf = DirectiveConf(NGX_RS_HTTP_SRV_CONF_OFFSET);
+    /// The module location configuration (`HTTPModule::LocConf`).
+    pub const LOC: DirectiveConf = DirectiveConf(NGX_RS_HTTP_LOC_CONF_OFFSET);
+}
@@ -0,0 +1,140 @@
+use crate::core::*;
+use crate::ffi::*;
+use crate::http::Request;
+
+/// Define the registration function of an [`HttpFilter`].
+///
+/// Generates `unsafe fn $name() -> Status` which inserts the filter at the top of the header and
+/// body filter chains and stores the previous top filters as the [`NextHeaderFilter`] and
+/// [`NextBodyFilter`] handles passed to the filter. The function must be called from
+/// [`HTTPModule::postconfiguration`](crate::http::HTTPModule::postconfiguration), and the module
+/// declared with `filter = true` in [`ngx_http_module`](crate::ngx_http_module), so that the
+/// filter is placed above the copy filter as for NGINX filter modules.
+///
+/// Body filters then get the response body in memory if the header filter calls
+/// [`Request::set_filter_need_in_memory`], rather than file buffers sent with `sendfile`.
+///
+/// ```rust,ignore
+/// struct ServerFilter;
+///
+/// impl HttpFilter for ServerFilter {
+///     fn header_filter(request: &mut Request, next: &NextHeaderFilter) -> Status {
+///         request.add_header_out("X-Server", "rust");
+///         next.call(request)
+///     }
+/// }
+///
+/// http_filter!(ngx_http_server_filter_init, ServerFilter);
+///
+/// impl HTTPModule for Module {
+///     // ...
+///     unsafe extern "C" fn postconfiguration(_cf: *mut ngx_conf_t) -> ngx_int_t {
+///         ngx_http_server_filter_init().into()
+///     }
+/// }
+///
+/// ngx_http_module!(ngx_http_server_filter_module, Module, filter = true);
+/// ```
+#[macro_export]
+macro_rules! http_filter {
+    ( $name: ident, $filter: ty ) => {
+        unsafe fn $name() -> $crate::core::Status {
+            static mut NEXT_HEADER_FILTER: $crate::ffi::ngx_http_output_header_filter_pt = None;
+            static mut NEXT_BODY_FILTER: $crate::ffi::ngx_http_output_body_filter_pt = None;
+
+            unsafe extern "C" fn header_filter(r: *mut $crate::ffi::ngx_http_request_t) -> $crate::ffi::ngx_int_t {
+                let next = $crate::http::NextHeaderFilter::new(NEXT_HEADER_FILTER);
+                let request = $crate::http::Request::from_ngx_http_request(r);
+                <$filter as $crate::http::HttpFilter>::header_filter(request, &next).0
+            }
+
+            unsafe extern "C" fn body_filter(
+                r: *mut $crate::ffi::ngx_http_request_t,
+                chain: *mut $crate::ffi::ngx_chain_t,
+            ) -> $crate::ffi::ngx_int_t {
+                let next = $crate::http::NextBodyFilter::new(NEXT_BODY_FILTER);
+                let request = $crate::http::Request::from_ngx_http_request(r);
+                let mut chain = $crate::core::Chain::from_ngx_chain(chain);
+                <$filter as $crate::http::HttpFilter>::body_filter(request, &mut chain, &next).0
+            }
+
+            NEXT_HEADER_FILTER = $crate::ffi::ngx_http_top_header_filter;
+            $crate::ffi::ngx_http_top_header_filter = Some(header_filter);
+
+            NEXT_BODY_FILTER = $crate::ffi::ngx_http_top_body_filter;
+            $crate::ffi::ngx_http_top_body_filter = Some(body_filter);
+
+            $crate::core::Status::NGX_OK
+        }
+    };
+}
+
+/// The `HttpFilter` trait provides the response [filter] interface.
+///
+/// Header filters run once per response before the headers are sent; body filters run for each
+/// part of the response body. Both should pass the (possibly modified) response to the next
+/// filter in the chain. The default implementations pass the response through unchanged.
+///
+/// Filters are registered with [`http_filter`](crate::http_filter).
+///
+/// [filter]: https://nginx.org/en/docs/dev/development_guide.html#http_response_body_filters
+pub trait HttpFilter {
+    /// Response header filter.
+    fn header_filter(request: &mut Request, next: &NextHeaderFilter) -> Status {
+        next.call(request)
+    }
+
+    /// Response body filter.
+    ///
+    /// `chain` is the part of the response body to process; it may be empty, e.g. when the
+    /// output is flushed.
+    fn body_filter(request: &mut Request, chain: &mut Chain, next: &NextBodyFilter) -> Status {
+        next.call(request, chain)
+    }
+}
+
+/// Handle to the next header filter in the chain.
+#[derive(Clone, Copy)]
+pub struct NextHeaderFilter(ngx_http_output_header_filter_pt);
+
+impl NextHeaderFilter {
+    /// Wrap the header filter which was at the top of the chain when the filter was registered.
+    ///
+    /// # Safety
+    /// `filter` must be `None` or a header filter of the NGINX filter chain.
+    pub unsafe fn new(filter: ngx_http_output_header_filter_pt) -> Self {
+        NextHeaderFilter(filter)
+    }
+
+    /// Call the next header filter.
+    pub fn call(&self, request: &mut Request) -> Status {
+        match self.0 {
+            // SAFETY: filters are set up by NGINX and other modules, and accept any valid request.
+            Some(filter) => unsafe { Status(filter(request.into())) },
+            None => Status::NGX_OK,
+        }
+    }
+}
+
+/// Handle to the next body filter in the chain.
+#[derive(Clone, Copy)]
+pub struct NextBodyFilter(ngx_http_output_body_filter_pt);
+
+impl NextBodyFilter {
+    /// Wrap the body filter which was at the top of the chain when the filter was registered.
+    ///
+    /// # Safety
+    /// `filter` must be `None` or a body filter of the NGINX filter chain.
+    pub unsafe fn new(filter: ngx_http_output_body_filter_pt) -> Self {
+        NextBodyFilter(filter)
+    }
+
+    /// Call the next body filter.
+    pub fn call(&self, request: &mut Request, chain: &mut Chain) -> Status {
+        match self.0 {
+            // SAFETY: filters are set up by NGINX and other modules, and accept any valid request.
+            Some(filter) => unsafe { Status(filter(request.into(), chain.as_ngx_chain())) },
+            None => Status::NGX_OK,
+        }
+    }
+}
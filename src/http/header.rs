@@ -0,0 +1,636 @@
+use crate::core::*;
+use crate::ffi::*;
+use crate::http::Request;
+
+use std::marker::PhantomData;
+use std::mem;
+use std::os::raw::c_void;
+use std::time::{SystemTime, UNIX_EPOCH};
+use std::{ptr, slice};
+
+/// A request or response header, borrowing an [`ngx_table_elt_t`] from the request.
+///
+/// [`ngx_table_elt_t`]: https://nginx.org/en/docs/dev/development_guide.html#http_request
+#[repr(transparent)]
+pub struct Header(ngx_table_elt_t);
+
+impl Header {
+    /// Header name, as received or set.
+    pub fn key(&self) -> &NgxStr {
+        unsafe { NgxStr::from_ngx_str(self.0.key) }
+    }
+
+    /// Header value.
+    pub fn value(&self) -> &NgxStr {
+        unsafe { NgxStr::from_ngx_str(self.0.value) }
+    }
+
+    /// Header name in lowercase, if set.
+    pub fn lowcase_key(&self) -> Option<&[u8]> {
+        if self.0.lowcase_key.is_null() {
+            return None;
+        }
+        Some(unsafe { slice::from_raw_parts(self.0.lowcase_key, self.0.key.len) })
+    }
+
+    /// Hash of the lowercase header name, or 0 if the header is deleted.
+    pub fn hash(&self) -> ngx_uint_t {
+        self.0.hash
+    }
+
+    /// Next header with the same name, for headers nginx links together, e.g. `Cookie` in
+    /// `headers_in` or `Set-Cookie` in upstream responses.
+    pub fn next(&self) -> Option<&Header> {
+        unsafe { self.0.next.cast::<Header>().as_ref() }
+    }
+
+    /// Returns `true` if the header name is `name`, compared case-insensitively.
+    pub fn is(&self, name: &str) -> bool {
+        self.key().as_bytes().eq_ignore_ascii_case(name.as_bytes())
+    }
+
+    /// Returns the underlying `ngx_table_elt_t` pointer.
+    pub fn as_ngx_table_elt(&self) -> *const ngx_table_elt_t {
+        &self.0
+    }
+}
+
+/// Headers of a request, borrowing its `headers_in` or `headers_out` list.
+///
+/// Deleted headers, with a zero hash, are skipped. Headers are compared case-insensitively,
+/// without allocating.
+#[derive(Clone, Copy)]
+pub struct Headers<'a> {
+    list: *const ngx_list_t,
+    _marker: PhantomData<&'a ngx_list_t>,
+}
+
+impl<'a> Headers<'a> {
+    /// Create a [`Headers`] from an `ngx_list_t` of `ngx_table_elt_t`.
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid, initialized list of `ngx_table_elt_t`, which is not
+    /// modified for the lifetime of the returned `Headers`.
+    pub unsafe fn from_ngx_list(list: *const ngx_list_t) -> Headers<'a> {
+        Headers {
+            list,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Iterates over the headers in order.
+    pub fn iter(&self) -> HeaderIter<'a> {
+        let part = unsafe { ptr::addr_of!((*self.list).part) };
+        HeaderIter {
+            part,
+            i: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    /// First header named `name`.
+    pub fn get(&self, name: &str) -> Option<&'a Header> {
+        self.iter().find(|h| h.is(name))
+    }
+
+    /// All headers named `name`, in order.
+    pub fn get_all<'n>(&self, name: &'n str) -> impl Iterator<Item = &'a Header> + 'n
+    where
+        'a: 'n,
+    {
+        self.iter().filter(move |h| h.is(name))
+    }
+}
+
+impl<'a> IntoIterator for Headers<'a> {
+    type Item = &'a Header;
+    type IntoIter = HeaderIter<'a>;
+
+    fn into_iter(self) -> HeaderIter<'a> {
+        self.iter()
+    }
+}
+
+/// Iterator over [`Headers`].
+pub struct HeaderIter<'a> {
+    part: *const ngx_list_part_t,
+    i: ngx_uint_t,
+    _marker: PhantomData<&'a ngx_list_t>,
+}
+
+impl<'a> Iterator for HeaderIter<'a> {
+    type Item = &'a Header;
+
+    fn next(&mut self) -> Option<&'a Header> {
+        unsafe {
+            loop {
+                if self.i >= (*self.part).nelts {
+                    if (*self.part).next.is_null() {
+                        return None;
+                    }
+                    self.part = (*self.part).next;
+                    self.i = 0;
+                    continue;
+                }
+
+                let header = &*((*self.part).elts as *const Header).add(self.i);
+                self.i += 1;
+                if header.0.hash != 0 {
+                    return Some(header);
+                }
+            }
+        }
+    }
+}
+
+impl Request {
+    /// Request headers.
+    pub fn headers_in(&self) -> Headers<'_> {
+        let r: *const ngx_http_request_t = self.into();
+        unsafe { Headers::from_ngx_list(ptr::addr_of!((*r).headers_in.headers)) }
+    }
+
+    /// Response headers, except for those nginx generates from `headers_out` fields, e.g.
+    /// `Content-Length`, unless set explicitly.
+    pub fn headers_out(&self) -> Headers<'_> {
+        let r: *const ngx_http_request_t = self.into();
+        unsafe { Headers::from_ngx_list(ptr::addr_of!((*r).headers_out.headers)) }
+    }
+
+    /// Value of the first request header named `name`.
+    pub fn header_in(&self, name: &str) -> Option<&NgxStr> {
+        self.headers_in().get(name).map(Header::value)
+    }
+
+    /// Value of the first response header named `name`.
+    pub fn header_out(&self, name: &str) -> Option<&NgxStr> {
+        self.headers_out().get(name).map(Header::value)
+    }
+
+    /// Replace the request headers named `key` with a single header.
+    ///
+    /// The `headers_in` field nginx keeps for the header, e.g. `headers_in.host`, points to the
+    /// new header; `Host` also sets the server name of `$host`, and `Content-Length` the request
+    /// body length. Returns `None` if the `Host` value is not a valid host, as checked by nginx for
+    /// the request line and headers, or if memory allocation fails.
+    pub fn set_header_in(&mut self, key: &str, value: &str) -> Option<()> {
+        let host = if key.eq_ignore_ascii_case("Host") {
+            Some(validate_host(value)?)
+        } else {
+            None
+        };
+
+        self.remove_header_in(key);
+
+        let r: *mut ngx_http_request_t = self.into();
+        unsafe {
+            let h = ngx_list_push(&mut (*r).headers_in.headers) as *mut ngx_table_elt_t;
+            add_to_ngx_table(h, (*r).pool, key, value)?;
+            // Request headers are looked up by their hash, e.g. by `proxy_set_header`.
+            (*h).hash = ngx_hash_key((*h).lowcase_key, (*h).key.len);
+
+            if let Some(ph) = header_in_shortcut(r, key) {
+                *ph = h;
+            }
+
+            if let Some(host) = host {
+                (*r).headers_in.server = self.pool().alloc_str(host.to_ascii_lowercase().as_bytes())?;
+            } else if key.eq_ignore_ascii_case("Content-Length") {
+                (*r).headers_in.content_length_n = parse_content_length(value);
+            }
+        }
+        Some(())
+    }
+
+    /// Remove all request headers named `key`.
+    ///
+    /// The headers are unlinked from the list, so they are not passed to proxied servers, and
+    /// the `headers_in` field nginx keeps for the header is cleared. Returns `false` if there was
+    /// no such header.
+    pub fn remove_header_in(&mut self, key: &str) -> bool {
+        let r: *mut ngx_http_request_t = self.into();
+        unsafe {
+            if !unlink_headers(&mut (*r).headers_in.headers, key) {
+                return false;
+            }
+
+            if let Some(ph) = header_in_shortcut(r, key) {
+                *ph = ptr::null_mut();
+            }
+
+            if key.eq_ignore_ascii_case("Host") {
+                (*r).headers_in.server = crate::ngx_null_string!();
+            } else if key.eq_ignore_ascii_case("Content-Length") {
+                (*r).headers_in.content_length_n = -1;
+            }
+        }
+        true
+    }
+
+    /// Replace the response headers named `key` with a single header.
+    ///
+    /// The `headers_out` field nginx keeps for the header points to the new header, or holds its
+    /// value: `Content-Type` is only stored in `headers_out.content_type`, while `Content-Length`
+    /// and `Last-Modified` also set `content_length_n` and `last_modified_time`. Returns `None`
+    /// if memory allocation fails.
+    pub fn set_header_out(&mut self, key: &str, value: &str) -> Option<()> {
+        self.remove_header_out(key);
+        if key.eq_ignore_ascii_case("Content-Type") {
+            return self.set_content_type(value);
+        }
+
+        let r: *mut ngx_http_request_t = self.into();
+        unsafe {
+            let h = ngx_list_push(&mut (*r).headers_out.headers) as *mut ngx_table_elt_t;
+            add_to_ngx_table(h, (*r).pool, key, value)?;
+
+            if let Some(ph) = header_out_shortcut(r, key) {
+                *ph = h;
+            }
+
+            if key.eq_ignore_ascii_case("Content-Length") {
+                (*r).headers_out.content_length_n = parse_content_length(value);
+            } else if key.eq_ignore_ascii_case("Last-Modified") {
+                (*r).headers_out.last_modified_time = ngx_parse_http_time(value.as_ptr() as *mut u_char, value.len());
+            }
+        }
+        Some(())
+    }
+
+    /// Remove all response headers named `key`, marking them as deleted.
+    ///
+    /// The `headers_out` field nginx keeps for the header is cleared. nginx still generates the
+    /// `Server` and `Date` headers if they are not set. Returns `false` if there was no such
+    /// header.
+    pub fn remove_header_out(&mut self, key: &str) -> bool {
+        let r: *mut ngx_http_request_t = self.into();
+        unsafe {
+            if let Some(ph) = header_out_shortcut(r, key) {
+                *ph = ptr::null_mut();
+            }
+
+            let headers_out = &mut (*r).headers_out;
+            let mut removed = mark_headers_deleted(&mut headers_out.headers, key);
+
+            if key.eq_ignore_ascii_case("Content-Type") {
+                removed |= headers_out.content_type.len != 0;
+                headers_out.content_type_len = 0;
+                headers_out.content_type = crate::ngx_null_string!();
+                headers_out.content_type_lowcase = ptr::null_mut();
+                headers_out.content_type_hash = 0;
+            } else if key.eq_ignore_ascii_case("Content-Length") {
+                removed |= headers_out.content_length_n != -1;
+                headers_out.content_length_n = -1;
+            } else if key.eq_ignore_ascii_case("Last-Modified") {
+                removed |= headers_out.last_modified_time != -1;
+                headers_out.last_modified_time = -1;
+            }
+            removed
+        }
+    }
+
+    /// Set the response [Content-Type], e.g. `text/html` or `text/html; charset=utf-8`.
+    ///
+    /// As for proxied responses, a `charset` parameter also sets the response charset, which
+    /// the charset filter converts from; without one, a previously set charset is reset.
+    /// Returns `None` if memory allocation fails.
+    ///
+    /// [Content-Type]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Content-Type
+    pub fn set_content_type(&mut self, content_type: &str) -> Option<()> {
+        let (mime_type, charset) = split_content_type(content_type);
+        let mut pool = self.pool();
+        let content_type = pool.alloc_str(content_type.as_bytes())?;
+        let charset = match charset {
+            Some(charset) => pool.alloc_str(charset.as_bytes())?,
+            None => crate::ngx_null_string!(),
+        };
+        let r: *mut ngx_http_request_t = self.into();
+        unsafe {
+            let headers_out = &mut (*r).headers_out;
+            headers_out.content_type = content_type;
+            headers_out.charset = charset;
+            // Only the MIME type is matched by `gzip_types`, `charset_types` and others.
+            headers_out.content_type_len = mime_type.len();
+            headers_out.content_type_lowcase = ptr::null_mut();
+            headers_out.content_type_hash = 0;
+        }
+        Some(())
+    }
+
+    /// Remove response body [Content-Length], e.g. in a filter that changes the body length.
+    ///
+    /// [Content-Length]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Content-Length
+    pub fn clear_content_length(&mut self) {
+        self.remove_header_out("Content-Length");
+    }
+
+    /// Set the response charset, e.g. `utf-8`.
+    ///
+    /// nginx appends it to the `Content-Type` header, unless the content type already has
+    /// parameters. Returns `None` if memory allocation fails.
+    pub fn set_charset(&mut self, charset: &str) -> Option<()> {
+        let charset = self.pool().alloc_str(charset.as_bytes())?;
+        let r: *mut ngx_http_request_t = self.into();
+        unsafe { (*r).headers_out.charset = charset };
+        Some(())
+    }
+
+    /// Set the response [Last-Modified] time, used by the not modified and range filters.
+    ///
+    /// The header is generated by nginx from the time, replacing any `Last-Modified` header.
+    ///
+    /// [Last-Modified]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Last-Modified
+    pub fn set_last_modified(&mut self, time: SystemTime) {
+        self.remove_header_out("Last-Modified");
+        let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let r: *mut ngx_http_request_t = self.into();
+        unsafe { (*r).headers_out.last_modified_time = secs.min(time_t::MAX as u64) as time_t };
+    }
+
+    /// Set the response [ETag], including its quotes, e.g. `"5e2b"` or `W/"5e2b"`.
+    ///
+    /// Returns `None` if memory allocation fails.
+    ///
+    /// [ETag]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/ETag
+    pub fn set_etag(&mut self, etag: &str) -> Option<()> {
+        self.set_header_out("ETag", etag)
+    }
+
+    /// Set the response [Location].
+    ///
+    /// nginx makes a location starting with `/` absolute, unless `absolute_redirect` is off.
+    /// Returns `None` if memory allocation fails.
+    ///
+    /// [Location]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Location
+    pub fn set_location(&mut self, location: &str) -> Option<()> {
+        self.set_header_out("Location", location)
+    }
+
+    /// Set the response [Cache-Control].
+    ///
+    /// Returns `None` if memory allocation fails.
+    ///
+    /// [Cache-Control]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Cache-Control
+    pub fn set_cache_control(&mut self, cache_control: &str) -> Option<()> {
+        self.set_header_out("Cache-Control", cache_control)
+    }
+
+    /// Set the response status line sent instead of the one derived from the status, without
+    /// the protocol, e.g. `299 Custom`.
+    ///
+    /// Only used by HTTP/1.x. Returns `None` if memory allocation fails.
+    pub fn set_status_line(&mut self, status_line: &str) -> Option<()> {
+        let status_line = self.pool().alloc_str(status_line.as_bytes())?;
+        let r: *mut ngx_http_request_t = self.into();
+        unsafe { (*r).headers_out.status_line = status_line };
+        Some(())
+    }
+}
+
+/// Field of `headers_in` pointing to the header `key`, as listed in `ngx_http_headers_in`.
+unsafe fn header_in_shortcut(r: *mut ngx_http_request_t, key: &str) -> Option<*mut *mut ngx_table_elt_t> {
+    let mut hh = ptr::addr_of!(ngx_http_headers_in) as *const ngx_http_header_t;
+    while (*hh).name.len != 0 {
+        if (*hh).offset != 0
+            && NgxStr::from_ngx_str((*hh).name)
+                .as_bytes()
+                .eq_ignore_ascii_case(key.as_bytes())
+        {
+            let headers_in = ptr::addr_of_mut!((*r).headers_in) as *mut u8;
+            return Some(headers_in.add((*hh).offset) as *mut *mut ngx_table_elt_t);
+        }
+        hh = hh.add(1);
+    }
+    None
+}
+
+/// Field of `headers_out` pointing to the header `key`.
+unsafe fn header_out_shortcut(r: *mut ngx_http_request_t, key: &str) -> Option<*mut *mut ngx_table_elt_t> {
+    let headers_out = ptr::addr_of_mut!((*r).headers_out);
+    // Not listed in `ngx_http_headers_out`.
+    let fields = [
+        ("Refresh", ptr::addr_of_mut!((*headers_out).refresh)),
+        ("Content-Range", ptr::addr_of_mut!((*headers_out).content_range)),
+        ("WWW-Authenticate", ptr::addr_of_mut!((*headers_out).www_authenticate)),
+        ("Link", ptr::addr_of_mut!((*headers_out).link)),
+    ];
+    if let Some((_, ph)) = fields.iter().find(|(name, _)| name.eq_ignore_ascii_case(key)) {
+        return Some(*ph);
+    }
+
+    let mut hh = ptr::addr_of!(ngx_http_headers_out) as *const ngx_http_header_out_t;
+    while (*hh).name.len != 0 {
+        if (*hh).offset != 0
+            && NgxStr::from_ngx_str((*hh).name)
+                .as_bytes()
+                .eq_ignore_ascii_case(key.as_bytes())
+        {
+            return Some((headers_out as *mut u8).add((*hh).offset) as *mut *mut ngx_table_elt_t);
+        }
+        hh = hh.add(1);
+    }
+    None
+}
+
+/// Marks the headers named `key` in `list` as deleted, skipped by the header filter.
+unsafe fn mark_headers_deleted(list: *mut ngx_list_t, key: &str) -> bool {
+    let mut removed = false;
+    let mut part = ptr::addr_of_mut!((*list).part);
+
+    while !part.is_null() {
+        let elts = (*part).elts as *mut ngx_table_elt_t;
+        for i in 0..(*part).nelts {
+            let h = elts.add(i);
+            if (*h).hash != 0
+                && NgxStr::from_ngx_str((*h).key)
+                    .as_bytes()
+                    .eq_ignore_ascii_case(key.as_bytes())
+            {
+                (*h).hash = 0;
+                removed = true;
+            }
+        }
+        part = (*part).next;
+    }
+    removed
+}
+
+/// Unlinks the headers named `key` from `list`, marking them as deleted.
+///
+/// As pointers to headers are kept in `headers_in` and `next` links, other headers are not moved:
+/// the list part holding a header is split after it instead, as done by the headers-more module.
+unsafe fn unlink_headers(list: *mut ngx_list_t, key: &str) -> bool {
+    let mut removed = false;
+    let mut part = ptr::addr_of_mut!((*list).part);
+
+    while !part.is_null() {
+        let elts = (*part).elts as *mut ngx_table_elt_t;
+        let mut i = 0;
+        while i < (*part).nelts {
+            let h = elts.add(i);
+            if !NgxStr::from_ngx_str((*h).key)
+                .as_bytes()
+                .eq_ignore_ascii_case(key.as_bytes())
+            {
+                i += 1;
+                continue;
+            }
+
+            (*h).hash = 0;
+            removed = true;
+
+            if i + 1 < (*part).nelts {
+                let rest = ngx_palloc((*list).pool, mem::size_of::<ngx_list_part_t>()) as *mut ngx_list_part_t;
+                if rest.is_null() {
+                    // Keep the header, marked as deleted.
+                    i += 1;
+                    continue;
+                }
+                (*rest).elts = elts.add(i + 1) as *mut c_void;
+                (*rest).nelts = (*part).nelts - i - 1;
+                (*rest).next = (*part).next;
+                (*part).next = rest;
+
+                if (*list).last == part {
+                    // `ngx_list_push` assumes the last part has room for `nalloc` elements.
+                    let last = ngx_palloc((*list).pool, mem::size_of::<ngx_list_part_t>()) as *mut ngx_list_part_t;
+                    let last_elts = ngx_palloc((*list).pool, (*list).nalloc * (*list).size);
+                    if last.is_null() || last_elts.is_null() {
+                        (*part).next = (*rest).next;
+                        i += 1;
+                        continue;
+                    }
+                    (*last).elts = last_elts;
+                    (*last).nelts = 0;
+                    (*last).next = ptr::null_mut();
+                    (*rest).next = last;
+                    (*list).last = last;
+                }
+            }
+            // The last header of the part is dropped, and the part continues with `rest`.
+            (*part).nelts = i;
+            break;
+        }
+        part = (*part).next;
+    }
+    removed
+}
+
+fn parse_content_length(value: &str) -> off_t {
+    // As `ngx_atoof`: digits only, and -1 if empty or on overflow.
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return -1;
+    }
+    value
+        .bytes()
+        .try_fold(0 as off_t, |n, b| n.checked_mul(10)?.checked_add((b - b'0') as off_t))
+        .unwrap_or(-1)
+}
+
+/// MIME type of a `Content-Type` header value, and its `charset` parameter.
+fn split_content_type(value: &str) -> (&str, Option<&str>) {
+    let mut params = value.split(';');
+    let mime_type = params.next().unwrap_or_default().trim_end();
+    let charset = params.find_map(|param| {
+        let (name, value) = param.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches('"'))
+    });
+    (mime_type, charset)
+}
+
+/// Host name of a `Host` header value, without port and trailing dot, as `ngx_http_validate_host`.
+///
+/// Returns `None` if the host is empty, or contains consecutive dots, path separators or control
+/// characters.
+fn validate_host(value: &str) -> Option<&str> {
+    let mut dot_pos = None;
+    let mut host_len = value.len();
+    let mut literal = false;
+    let mut rest = false;
+
+    for (i, ch) in value.bytes().enumerate() {
+        match ch {
+            b'.' => {
+                if dot_pos.is_some_and(|pos| pos + 1 == i) {
+                    return None;
+                }
+                dot_pos = Some(i);
+            }
+            b':' if !literal && !rest => {
+                host_len = i;
+                rest = true;
+            }
+            b'[' if i == 0 => literal = true,
+            b']' if literal && !rest => {
+                host_len = i + 1;
+                rest = true;
+            }
+            b'/' => return None,
+            ch if ch <= 0x20 || ch == 0x7f => return None,
+            _ => {}
+        }
+    }
+
+    if dot_pos.is_some_and(|pos| pos + 1 == host_len) {
+        host_len -= 1;
+    }
+    if host_len == 0 {
+        return None;
+    }
+    Some(&value[..host_len])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_content_length() {
+        assert_eq!(parse_content_length("0"), 0);
+        assert_eq!(parse_content_length("1234"), 1234);
+        assert_eq!(parse_content_length(""), -1);
+        assert_eq!(parse_content_length("-1"), -1);
+        assert_eq!(parse_content_length(" 12"), -1);
+        assert_eq!(parse_content_length("12a"), -1);
+        assert_eq!(parse_content_length("99999999999999999999"), -1);
+    }
+
+    #[test]
+    fn test_split_content_type() {
+        assert_eq!(split_content_type("text/html"), ("text/html", None));
+        assert_eq!(
+            split_content_type("text/html; charset=utf-8"),
+            ("text/html", Some("utf-8"))
+        );
+        assert_eq!(
+            split_content_type("text/html;Charset=\"utf-8\""),
+            ("text/html", Some("utf-8"))
+        );
+        assert_eq!(
+            split_content_type("multipart/mixed; boundary=x; charset=koi8-r"),
+            ("multipart/mixed", Some("koi8-r"))
+        );
+        assert_eq!(split_content_type("text/html ; charset"), ("text/html", None));
+    }
+
+    #[test]
+    fn test_validate_host() {
+        assert_eq!(validate_host("example.com"), Some("example.com"));
+        assert_eq!(validate_host("example.com:8080"), Some("example.com"));
+        assert_eq!(validate_host("host.:80"), Some("host"));
+        assert_eq!(validate_host("host."), Some("host"));
+        assert_eq!(validate_host("[::1]"), Some("[::1]"));
+        assert_eq!(validate_host("[::1]:80"), Some("[::1]"));
+        assert_eq!(validate_host(""), None);
+        assert_eq!(validate_host("."), None);
+        assert_eq!(validate_host(":80"), None);
+        assert_eq!(validate_host("a..b"), None);
+        assert_eq!(validate_host("a/b"), None);
+        assert_eq!(validate_host("a b"), None);
+        assert_eq!(validate_host("a\r\nb"), None);
+        assert_eq!(validate_host("a\x7f"), None);
+    }
+}
@@ -1,11 +1,37 @@
+mod args;
+mod body;
 mod conf;
+mod directive;
+mod filter;
+mod header;
 mod module;
+mod phase;
 mod request;
+mod slot;
 mod status;
+mod subrequest;
+mod thread;
 mod upstream;
+mod variable;
 
+pub use args::*;
+pub use body::*;
 pub use conf::*;
+pub use directive::*;
+pub use filter::*;
+pub use header::*;
 pub use module::*;
+pub use phase::*;
 pub use request::*;
+pub use slot::*;
 pub use status::*;
+pub use subrequest::*;
 pub use upstream::*;
+pub use variable::*;
+
+// Shared with the other module types, and re-exported for HTTP modules.
+pub use crate::core::{
+    conf_set_slot, directive_set, merge_conf_option, merge_conf_value, ConfEnum, ConfSlot, DirectiveArgs,
+    DirectiveError, DirectiveHandler, EnumSlot, FlagSlot, Merge, MergeConfigError, MsecSlot, NumSlot, SizeSlot,
+    StrSlot,
+};
@@ -5,39 +5,132 @@ use crate::ffi::*;
 use core::ptr;
 use std::os::raw::{c_char, c_void};
 
-/// MergeConfigError - configuration cannot be merged with levels above.
-#[derive(Debug)]
-pub enum MergeConfigError {
-    /// No value provided for configuration argument
-    NoValue,
-}
+/// Define an HTTP module from an [`HTTPModule`] implementation.
+///
+/// Generates the `ngx_module_t` static named `$name` with its `ngx_http_module_t` context and
+/// exports it with [`ngx_modules`](crate::ngx_modules). The version and signature fields are taken
+/// from the NGINX the crate is built against.
+///
+/// Optional arguments:
+/// * `commands` - a null-terminated `ngx_command_t` array, e.g. defined with
+///   [`ngx_http_commands`](crate::ngx_http_commands).
+/// * `variables` - a null-terminated `ngx_http_variable_t` array. The variables are added in the
+///   preconfiguration stage, before calling [`HTTPModule::preconfiguration`].
+/// * `filter = true` - the module registers response filters with
+///   [`http_filter`](crate::http_filter). It is then loaded before `ngx_http_copy_filter_module`,
+///   so that its filters run above the copy filter, which reads file buffers into memory.
+///
+/// ```rust,ignore
+/// ngx_http_module!(ngx_http_curl_module, Module, commands = ngx_http_curl_commands);
+/// ```
+#[macro_export]
+macro_rules! ngx_http_module {
+    (
+        $name:ident, $module:ty
+        $(, commands = $commands:ident)?
+        $(, variables = $variables:ident)?
+        $(, filter = $filter:tt)?
+        $(,)?
+    ) => {
+        $crate::ngx_http_module!(@modules $name $(, $filter)?);
 
-impl std::error::Error for MergeConfigError {}
+        #[no_mangle]
+        pub static mut $name: $crate::ffi::ngx_module_t = $crate::ffi::ngx_module_t {
+            ctx_index: $crate::ffi::ngx_uint_t::MAX,
+            index: $crate::ffi::ngx_uint_t::MAX,
+            name: ::std::ptr::null_mut(),
+            spare0: 0,
+            spare1: 0,
+            version: $crate::ffi::nginx_version as $crate::ffi::ngx_uint_t,
+            signature: $crate::ffi::NGX_RS_MODULE_SIGNATURE.as_ptr() as *const ::std::os::raw::c_char,
 
-impl std::fmt::Display for MergeConfigError {
-    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> std::fmt::Result {
-        match self {
-            MergeConfigError::NoValue => "no value".fmt(fmt),
+            ctx: {
+                static CTX: $crate::ffi::ngx_http_module_t = $crate::ffi::ngx_http_module_t {
+                    preconfiguration: Some($crate::ngx_http_module!(@preconfiguration $module $(, $variables)?)),
+                    postconfiguration: Some(<$module as $crate::http::HTTPModule>::postconfiguration),
+                    create_main_conf: Some(<$module as $crate::http::HTTPModule>::create_main_conf),
+                    init_main_conf: Some(<$module as $crate::http::HTTPModule>::init_main_conf),
+                    create_srv_conf: Some(<$module as $crate::http::HTTPModule>::create_srv_conf),
+                    merge_srv_conf: Some(<$module as $crate::http::HTTPModule>::merge_srv_conf),
+                    create_loc_conf: Some(<$module as $crate::http::HTTPModule>::create_loc_conf),
+                    merge_loc_conf: Some(<$module as $crate::http::HTTPModule>::merge_loc_conf),
+                };
+                &CTX as *const _ as *mut _
+            },
+            commands: $crate::ngx_http_module!(@commands $($commands)?),
+            type_: $crate::ffi::NGX_HTTP_MODULE as $crate::ffi::ngx_uint_t,
+
+            init_master: None,
+            init_module: None,
+            init_process: None,
+            init_thread: None,
+            exit_thread: None,
+            exit_process: None,
+            exit_master: None,
+
+            spare_hook0: 0,
+            spare_hook1: 0,
+            spare_hook2: 0,
+            spare_hook3: 0,
+            spare_hook4: 0,
+            spare_hook5: 0,
+            spare_hook6: 0,
+            spare_hook7: 0,
+        };
+    };
+    (@modules $name:ident) => {
+        $crate::ngx_modules!($name);
+    };
+    (@modules $name:ident, false) => {
+        $crate::ngx_modules!($name);
+    };
+    (@modules $name:ident, true) => {
+        $crate::ngx_modules!($name; before = "ngx_http_copy_filter_module");
+    };
+    (@commands) => {
+        ::std::ptr::null_mut()
+    };
+    (@commands $commands:ident) => {
+        unsafe { ::std::ptr::addr_of_mut!($commands) as *mut $crate::ffi::ngx_command_t }
+    };
+    (@preconfiguration $module:ty) => {
+        <$module as $crate::http::HTTPModule>::preconfiguration
+    };
+    (@preconfiguration $module:ty, $variables:ident) => {{
+        unsafe extern "C" fn preconfiguration(cf: *mut $crate::ffi::ngx_conf_t) -> $crate::ffi::ngx_int_t {
+            let vars = ::std::ptr::addr_of_mut!($variables) as *mut $crate::ffi::ngx_http_variable_t;
+            let rc = $crate::http::add_variables(cf, vars);
+            if rc != $crate::core::Status::NGX_OK.into() {
+                return rc;
+            }
+            <$module as $crate::http::HTTPModule>::preconfiguration(cf)
         }
-    }
+        preconfiguration
+    }};
 }
 
-/// The `Merge` trait provides a method for merging configuration down through each level.
+/// Add a null-terminated array of variables to the HTTP configuration.
 ///
-/// A module configuration should implement this trait for setting its configuration throughout
-/// each level.
-pub trait Merge {
-    /// Module merge function.
-    ///
-    /// # Returns
-    /// Result, Ok on success or MergeConfigError on failure.
-    fn merge(&mut self, prev: &Self) -> Result<(), MergeConfigError>;
-}
-
-impl Merge for () {
-    fn merge(&mut self, _prev: &Self) -> Result<(), MergeConfigError> {
-        Ok(())
+/// For each variable, the handlers and data are copied to the variable registered with
+/// `ngx_http_add_variable`.
+///
+/// # Safety
+///
+/// Callers should provide a valid `ngx_conf_t` and a pointer to an array terminated by a
+/// variable with an empty name, such as [`ngx_http_null_variable`](crate::ngx_http_null_variable).
+pub unsafe fn add_variables(cf: *mut ngx_conf_t, mut vars: *mut ngx_http_variable_t) -> ngx_int_t {
+    while (*vars).name.len != 0 {
+        let v = &mut *vars;
+        let var = ngx_http_add_variable(cf, &mut v.name, v.flags);
+        if var.is_null() {
+            return Status::NGX_ERROR.into();
+        }
+        (*var).get_handler = v.get_handler;
+        (*var).set_handler = v.set_handler;
+        (*var).data = v.data;
+        vars = vars.add(1);
     }
+    Status::NGX_OK.into()
 }
 
 /// The `HTTPModule` trait provides the NGINX configuration stage interface.
@@ -0,0 +1,87 @@
+use crate::core::*;
+use crate::ffi::*;
+use crate::http::{ngx_http_conf_get_module_loc_conf, ngx_http_conf_get_module_main_conf};
+
+use std::ptr;
+
+/// Request processing phases a module can register a handler for.
+///
+/// See https://nginx.org/en/docs/dev/development_guide.html#http_phases for details.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    /// First phase, after the request headers are read.
+    PostRead,
+    /// Rewrite directives in the `server` block.
+    ServerRewrite,
+    /// Rewrite directives in the `location` block.
+    Rewrite,
+    /// Preparation for access control, e.g. limits.
+    PreAccess,
+    /// Access control.
+    Access,
+    /// Actions before generating content, e.g. `try_files`.
+    PreContent,
+    /// Content generation for all locations without a location content handler.
+    Content,
+    /// Request logging.
+    Log,
+}
+
+impl From<Phase> for ngx_http_phases {
+    fn from(phase: Phase) -> Self {
+        match phase {
+            Phase::PostRead => ngx_http_phases_NGX_HTTP_POST_READ_PHASE,
+            Phase::ServerRewrite => ngx_http_phases_NGX_HTTP_SERVER_REWRITE_PHASE,
+            Phase::Rewrite => ngx_http_phases_NGX_HTTP_REWRITE_PHASE,
+            Phase::PreAccess => ngx_http_phases_NGX_HTTP_PREACCESS_PHASE,
+            Phase::Access => ngx_http_phases_NGX_HTTP_ACCESS_PHASE,
+            Phase::PreContent => ngx_http_phases_NGX_HTTP_PRECONTENT_PHASE,
+            Phase::Content => ngx_http_phases_NGX_HTTP_CONTENT_PHASE,
+            Phase::Log => ngx_http_phases_NGX_HTTP_LOG_PHASE,
+        }
+    }
+}
+
+/// HTTP request handler, as defined with [`http_request_handler`](crate::http_request_handler).
+pub type HttpHandler = unsafe extern "C" fn(r: *mut ngx_http_request_t) -> ngx_int_t;
+
+/// Register a handler for a request processing phase.
+///
+/// This must be called from [`HTTPModule::postconfiguration`](crate::http::HTTPModule::postconfiguration),
+/// where `cf` is the configuration of the `http` block.
+///
+/// ```rust,ignore
+/// unsafe extern "C" fn postconfiguration(cf: *mut ngx_conf_t) -> ngx_int_t {
+///     http::register_phase_handler(&mut *cf, http::Phase::Access, curl_access_handler).into()
+/// }
+/// ```
+pub fn register_phase_handler(cf: &mut ngx_conf_t, phase: Phase, handler: HttpHandler) -> Status {
+    // SAFETY: `cf` is the `http` block configuration, where `ngx_http_core_module` main configuration
+    // is always present.
+    unsafe {
+        let cmcf = ngx_http_conf_get_module_main_conf(cf, &*ptr::addr_of!(ngx_http_core_module));
+        let phase: ngx_http_phases = phase.into();
+        let h = ngx_array_push(&mut (*cmcf).phases[phase as usize].handlers) as *mut ngx_http_handler_pt;
+        if h.is_null() {
+            return Status::NGX_ERROR;
+        }
+        *h = Some(handler);
+    }
+    Status::NGX_OK
+}
+
+/// Set the content handler of the current location.
+///
+/// Unlike a [`Phase::Content`] handler, the location content handler only runs for requests in
+/// the location and replaces the standard content handlers, like `proxy_pass` does.
+///
+/// This must be called from a directive handler in the `location` context, where `cf` is the
+/// location configuration.
+pub fn set_location_handler(cf: &mut ngx_conf_t, handler: HttpHandler) {
+    // SAFETY: `cf` is a `location` block configuration, where `ngx_http_core_module` location
+    // configuration is always present.
+    unsafe {
+        let clcf = ngx_http_conf_get_module_loc_conf(cf, &*ptr::addr_of!(ngx_http_core_module));
+        (*clcf).handler = Some(handler);
+    }
+}
@@ -1,6 +1,7 @@
 use crate::core::*;
 use crate::ffi::*;
 use crate::http::status::*;
+use crate::http::Phase;
 use crate::ngx_null_string;
 use std::fmt;
 use std::os::raw::c_void;
@@ -245,6 +246,11 @@ impl Request {
         unsafe { add_to_ngx_table(table, self.0.pool, key, value) }
     }
 
+    /// HTTP status of response.
+    pub fn status(&self) -> HTTPStatus {
+        HTTPStatus(self.0.headers_out.status)
+    }
+
     /// Set response body [Content-Length].
     ///
     /// [Content-Length]: https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Content-Length
@@ -259,6 +265,81 @@ impl Request {
         unsafe { Status(ngx_http_send_header(&mut self.0)) }
     }
 
+    /// Send a special response buffer: `NGX_HTTP_LAST` ends the response body, and
+    /// `NGX_HTTP_FLUSH` flushes the buffered output.
+    pub fn send_special(&mut self, flags: ngx_uint_t) -> Status {
+        unsafe { Status(ngx_http_send_special(&mut self.0, flags)) }
+    }
+
+    /// Send a complete response with `status`, `content_type` and `body`.
+    ///
+    /// The request body is discarded, and the body is not sent for `HEAD` requests. With an empty
+    /// `content_type`, nginx sets the `Content-Type` from the URI extension and `types`, or
+    /// `default_type`. A content handler must return the returned status, with which nginx then
+    /// finalizes the request.
+    ///
+    /// The redirect statuses 301, 302, 303, 307 and 308 are rejected with `NGX_ERROR`, as nginx
+    /// would send the body as the `Location` header: use [`Request::redirect`] instead. Return an
+    /// error status for nginx to send its error page, or the page set with `error_page`.
+    pub fn send_response(&mut self, status: HTTPStatus, content_type: &str, body: impl AsRef<[u8]>) -> Status {
+        if is_redirect(status) {
+            return Status::NGX_ERROR;
+        }
+
+        let mut pool = self.pool();
+        let Some(body) = pool.alloc_str(body.as_ref()) else {
+            return Status::NGX_ERROR;
+        };
+        let content_type = if content_type.is_empty() {
+            None
+        } else {
+            match pool.alloc_str(content_type.as_bytes()) {
+                Some(content_type) => Some(content_type),
+                None => return Status::NGX_ERROR,
+            }
+        };
+        send_response_value(self, status, content_type, body)
+    }
+
+    /// Redirect the client to `location` with `status`, one of 301, 302, 303, 307 and 308, e.g.
+    /// [`HTTPStatus::MOVED_TEMPORARILY`]. Other statuses are rejected with `NGX_ERROR`; use
+    /// [`Request::set_location`] to send a `Location` header with them, e.g. for 201.
+    ///
+    /// nginx makes a location starting with `/` absolute, unless `absolute_redirect` is off, and
+    /// sends a body for the status, or the page set with `error_page`. A content handler must
+    /// return the returned status, with which nginx then finalizes the request.
+    pub fn redirect(&mut self, status: HTTPStatus, location: &str) -> Status {
+        if !is_redirect(status) {
+            return Status::NGX_ERROR;
+        }
+
+        let mut pool = self.pool();
+        let Some(location) = pool.alloc_str(location.as_bytes()) else {
+            return Status::NGX_ERROR;
+        };
+        send_response_value(self, status, None, location)
+    }
+
+    /// Finalize the request with `status`, e.g. once an asynchronous operation is complete.
+    ///
+    /// Unless the response header is already sent, an HTTP error status sends the error page for
+    /// the status, or the page set with `error_page`.
+    ///
+    /// # Safety
+    /// The request may be freed once finalized: it must not be used after this call, through this
+    /// reference or any other. This must not be called by a handler which returns the status to
+    /// nginx, and the caller must hold a reference count of the request, released by this call,
+    /// e.g. taken by a handler returning `NGX_DONE`.
+    pub unsafe fn finalize(&mut self, status: Status) {
+        unsafe { ngx_http_finalize_request(&mut self.0, status.0) }
+    }
+
+    /// Ask the copy filter to read the response body into memory, e.g. from a header filter whose
+    /// body filter reads the body, rather than passing file buffers to be sent with `sendfile`.
+    pub fn set_filter_need_in_memory(&mut self) {
+        self.0.set_filter_need_in_memory(1);
+    }
+
     /// Flag indicating that the output does not require a body.
     ///
     /// For example, this flag is used by `HTTP HEAD` requests.
@@ -292,40 +373,126 @@ impl Request {
     }
 
     /// Perform internal redirect to a location
+    ///
+    /// Returns `NGX_ERROR` if the location is empty. The redirect is not checked, see
+    /// [`Request::try_internal_redirect`] to pass query args and check the redirect.
+    #[deprecated(note = "use `Request::try_internal_redirect`, which checks the redirect")]
     pub fn internal_redirect(&self, location: &str) -> Status {
-        assert!(!location.is_empty(), "uri location is empty");
-        let uri_ptr = unsafe { &mut ngx_str_t::from_str(self.0.pool, location) as *mut _ };
+        if location.is_empty() {
+            return Status::NGX_ERROR;
+        }
+        let Some(mut uri) = self.pool().alloc_str(location.as_bytes()) else {
+            return Status::NGX_ERROR;
+        };
+        let uri_ptr = &mut uri as *mut _;
 
-        // FIXME: check status of ngx_http_named_location or ngx_http_internal_redirect
-        if location.starts_with('@') {
-            unsafe {
-                ngx_http_named_location((self as *const Request as *mut Request).cast(), uri_ptr);
-            }
+        let rc = if location.starts_with('@') {
+            unsafe { ngx_http_named_location((self as *const Request as *mut Request).cast(), uri_ptr) }
         } else {
             unsafe {
                 ngx_http_internal_redirect(
                     (self as *const Request as *mut Request).cast(),
                     uri_ptr,
                     std::ptr::null_mut(),
-                );
+                )
             }
+        };
+        Status(rc)
+    }
+
+    /// Perform an [internal redirect] to `uri` with the query string `args`, or to the named
+    /// location `uri` starting with `@`.
+    ///
+    /// `phase` is the phase of the calling handler, [`Phase::Content`] for a location content
+    /// handler, which must return the returned `NGX_DONE` status. The request is then processed
+    /// again in the new location, and finalized by nginx.
+    ///
+    /// Returns an error without redirecting the request if the named location does not exist, or
+    /// if the request was redirected too many times, e.g. by a redirect loop.
+    ///
+    /// [internal redirect]: https://nginx.org/en/docs/dev/development_guide.html#http_request_redirection
+    pub fn try_internal_redirect(
+        &mut self,
+        uri: &str,
+        args: Option<&str>,
+        phase: Phase,
+    ) -> Result<Status, InternalRedirectError> {
+        if uri.is_empty() {
+            return Err(InternalRedirectError::EmptyUri);
         }
-        Status::NGX_DONE
+        if phase == Phase::Log {
+            return Err(InternalRedirectError::InvalidPhase);
+        }
+
+        let r: *mut ngx_http_request_t = self.into();
+        let named = uri.starts_with('@');
+        unsafe {
+            // nginx finalizes the request with an error once the URI changes are exhausted.
+            if (*r).uri_changes() <= 1 {
+                return Err(InternalRedirectError::Cycle);
+            }
+            if named && !named_location_exists(r, uri) {
+                return Err(InternalRedirectError::LocationNotFound);
+            }
+        }
+
+        let mut pool = self.pool();
+        let mut uri = pool
+            .alloc_str(uri.as_bytes())
+            .ok_or(InternalRedirectError::AllocationFailed)?;
+        let mut args = match args {
+            Some(args) => Some(
+                pool.alloc_str(args.as_bytes())
+                    .ok_or(InternalRedirectError::AllocationFailed)?,
+            ),
+            None => None,
+        };
+
+        // Both functions increment the reference count, and return `NGX_DONE` even if the
+        // redirect fails later on, as the request is then finalized with an error.
+        let rc = unsafe {
+            if named {
+                // The named location keeps the current query string, unless replaced.
+                if let Some(args) = args {
+                    (*r).args = args;
+                    // `unparsed_uri` no longer matches the query string, e.g. for `proxy_pass`.
+                    (*r).set_valid_unparsed_uri(0);
+                }
+                ngx_http_named_location(r, &mut uri)
+            } else {
+                let args = args.as_mut().map_or(std::ptr::null_mut(), |args| args as *mut _);
+                ngx_http_internal_redirect(r, &mut uri, args)
+            }
+        };
+
+        // Unlike the content phase, other phases do not finalize the request with the status
+        // returned by the handler.
+        if phase != Phase::Content {
+            unsafe { ngx_http_finalize_request(r, Status::NGX_DONE.into()) };
+        }
+        Ok(Status(rc))
     }
 
     /// Send a subrequest
+    ///
+    /// See [`SubrequestBuilder`](crate::http::SubrequestBuilder) for in-memory subrequests with a
+    /// Rust completion closure.
     pub fn subrequest(
         &self,
         uri: &str,
         module: &ngx_module_t,
         post_callback: unsafe extern "C" fn(*mut ngx_http_request_t, *mut c_void, ngx_int_t) -> ngx_int_t,
     ) -> Status {
-        let uri_ptr = unsafe { &mut ngx_str_t::from_str(self.0.pool, uri) as *mut _ };
+        let Some(mut uri) = self.pool().alloc_str(uri.as_bytes()) else {
+            return Status::NGX_ERROR;
+        };
+        let uri_ptr = &mut uri as *mut _;
         // -------------
         // allocate memory and set values for ngx_http_post_subrequest_t
         let sub_ptr = self.pool().alloc(std::mem::size_of::<ngx_http_post_subrequest_t>());
-
-        // assert!(sub_ptr.is_null());
+        if sub_ptr.is_null() {
+            return Status::NGX_ERROR;
+        }
         let post_subreq = sub_ptr as *const ngx_http_post_subrequest_t as *mut ngx_http_post_subrequest_t;
         unsafe {
             (*post_subreq).handler = Some(post_callback);
@@ -345,7 +512,10 @@ impl Request {
             )
         };
 
-        // previously call of ngx_http_subrequest() would ensure that the pointer is not null anymore
+        // the subrequest is not created on failure, e.g. when the subrequest limit is reached
+        if r != Status::NGX_OK.into() || psr.is_null() {
+            return Status::NGX_ERROR;
+        }
         let sr = unsafe { &mut *psr };
 
         /*
@@ -363,12 +533,16 @@ impl Request {
 
     /// Iterate over headers_in
     /// each header item is (String, String) (copied)
+    ///
+    /// See [`Request::headers_in`] to borrow the headers instead.
     pub fn headers_in_iterator(&self) -> NgxListIterator {
         unsafe { list_iterator(&self.0.headers_in.headers) }
     }
 
     /// Iterate over headers_out
     /// each header item is (String, String) (copied)
+    ///
+    /// See [`Request::headers_out`] to borrow the headers instead.
     pub fn headers_out_iterator(&self) -> NgxListIterator {
         unsafe { list_iterator(&self.0.headers_out.headers) }
     }
@@ -378,6 +552,79 @@ impl Request {
 
 // }
 
+unsafe fn named_location_exists(r: *mut ngx_http_request_t, name: &str) -> bool {
+    let module = &*std::ptr::addr_of!(ngx_http_core_module);
+    let cscf = *(*r).srv_conf.add(module.ctx_index) as *mut ngx_http_core_srv_conf_t;
+    let mut clcfp = (*cscf).named_locations;
+    if clcfp.is_null() {
+        return false;
+    }
+    while !(*clcfp).is_null() {
+        if NgxStr::from_ngx_str((**clcfp).name).as_bytes() == name.as_bytes() {
+            return true;
+        }
+        clcfp = clcfp.add(1);
+    }
+    false
+}
+
+/// Statuses for which `ngx_http_send_response` sends its value as the `Location` header.
+fn is_redirect(status: HTTPStatus) -> bool {
+    matches!(
+        status,
+        HTTPStatus::MOVED_PERMANENTLY
+            | HTTPStatus::MOVED_TEMPORARILY
+            | HTTPStatus::SEE_OTHER
+            | HTTPStatus::TEMPORARY_REDIRECT
+            | HTTPStatus::PERMANENT_REDIRECT
+    )
+}
+
+/// Send the response with `ngx_http_send_response`, where `value` is the body, or the location for
+/// redirects.
+fn send_response_value(
+    request: &mut Request,
+    status: HTTPStatus,
+    content_type: Option<ngx_str_t>,
+    value: ngx_str_t,
+) -> Status {
+    let mut content_type = content_type;
+    // SAFETY: all-zero is a valid complex value, and without `lengths` its value is used as is.
+    let mut cv: ngx_http_complex_value_t = unsafe { std::mem::zeroed() };
+    cv.value = value;
+    let ct = content_type.as_mut().map_or(std::ptr::null_mut(), |ct| ct as *mut _);
+    unsafe { Status(ngx_http_send_response(request.into(), status.into(), ct, &mut cv)) }
+}
+
+/// Error of [`Request::try_internal_redirect`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InternalRedirectError {
+    /// The URI is empty.
+    EmptyUri,
+    /// The request cannot be redirected from the log phase.
+    InvalidPhase,
+    /// The request was redirected too many times.
+    Cycle,
+    /// The named location does not exist in the server.
+    LocationNotFound,
+    /// Memory allocation failed.
+    AllocationFailed,
+}
+
+impl Error for InternalRedirectError {}
+
+impl fmt::Display for InternalRedirectError {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            InternalRedirectError::EmptyUri => "empty URI".fmt(fmt),
+            InternalRedirectError::InvalidPhase => "redirect in log phase".fmt(fmt),
+            InternalRedirectError::Cycle => "rewrite or internal redirection cycle".fmt(fmt),
+            InternalRedirectError::LocationNotFound => "could not find named location".fmt(fmt),
+            InternalRedirectError::AllocationFailed => "memory allocation failed".fmt(fmt),
+        }
+    }
+}
+
 impl fmt::Debug for Request {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("Request").field("request_", &self.0).finish()
@@ -411,11 +658,6 @@ pub unsafe fn list_iterator(list: *const ngx_list_t) -> NgxListIterator {
 
 // iterator for ngx_list_t
 impl Iterator for NgxListIterator {
-    // type Item = (&str,&str);
-    // TODO: try to use str instead of string
-    // something like pub struct Header(ngx_table_elt_t);
-    // then header would have key and value
-
     type Item = (String, String);
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -556,6 +798,29 @@ impl Method {
             _ => Method(MethodInner::Unknown),
         }
     }
+
+    pub(crate) fn to_ngx(&self) -> ngx_uint_t {
+        let t = match self.0 {
+            MethodInner::Unknown => NGX_HTTP_UNKNOWN,
+            MethodInner::Get => NGX_HTTP_GET,
+            MethodInner::Head => NGX_HTTP_HEAD,
+            MethodInner::Post => NGX_HTTP_POST,
+            MethodInner::Put => NGX_HTTP_PUT,
+            MethodInner::Delete => NGX_HTTP_DELETE,
+            MethodInner::Mkcol => NGX_HTTP_MKCOL,
+            MethodInner::Copy => NGX_HTTP_COPY,
+            MethodInner::Move => NGX_HTTP_MOVE,
+            MethodInner::Options => NGX_HTTP_OPTIONS,
+            MethodInner::Propfind => NGX_HTTP_PROPFIND,
+            MethodInner::Proppatch => NGX_HTTP_PROPPATCH,
+            MethodInner::Lock => NGX_HTTP_LOCK,
+            MethodInner::Unlock => NGX_HTTP_UNLOCK,
+            MethodInner::Patch => NGX_HTTP_PATCH,
+            MethodInner::Trace => NGX_HTTP_TRACE,
+            MethodInner::Connect => NGX_HTTP_CONNECT,
+        };
+        t as _
+    }
 }
 
 impl AsRef<str> for Method {
@@ -0,0 +1,37 @@
+use crate::core::*;
+use crate::ffi::*;
+
+/// Slot for a directive compiled into a [complex value], analogous to
+/// `ngx_http_set_complex_value_slot`.
+///
+/// The value can be evaluated with [`Request::get_complex_value`](crate::http::Request::get_complex_value).
+///
+/// [complex value]: https://nginx.org/en/docs/dev/development_guide.html#http_complex_values
+pub struct ComplexValueSlot;
+
+impl ConfSlot for ComplexValueSlot {
+    type Field = Option<ngx_http_complex_value_t>;
+
+    fn set(cf: &mut ngx_conf_t, field: &mut Self::Field, args: &[&NgxStr]) -> Result<(), DirectiveError> {
+        if field.is_some() {
+            return Err(DirectiveError::Duplicate);
+        }
+
+        let value = args.first().copied().unwrap_or_default();
+        let mut s = ngx_str_t {
+            len: value.as_bytes().len(),
+            data: value.as_bytes().as_ptr() as *mut u_char,
+        };
+        // SAFETY: all-zero is the initial state nginx expects for both structures.
+        let cv = field.insert(unsafe { std::mem::zeroed() });
+        let mut ccv: ngx_http_compile_complex_value_t = unsafe { std::mem::zeroed() };
+        ccv.cf = cf;
+        ccv.value = &mut s;
+        ccv.complex_value = cv;
+
+        if unsafe { ngx_http_compile_complex_value(&mut ccv) } != NGX_OK as ngx_int_t {
+            return Err(DirectiveError::InvalidValue(value.to_string_lossy().into()));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,403 @@
+use crate::core::*;
+use crate::ffi::*;
+use crate::http::{HTTPStatus, Method, Request};
+
+use std::cell::{Cell, RefCell};
+use std::mem;
+use std::ops::{BitOr, BitOrAssign};
+use std::os::raw::c_void;
+use std::time::Duration;
+use std::{ptr, slice};
+
+/// Flags of a subrequest created with [`SubrequestBuilder`].
+///
+/// See https://nginx.org/en/docs/dev/development_guide.html#http_subrequests for details.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SubrequestFlags(pub ngx_uint_t);
+
+impl SubrequestFlags {
+    /// The response body is kept in memory instead of being sent to the client, and is available
+    /// with [`Request::in_memory_body`]. Its size is limited by `subrequest_output_buffer_size`.
+    pub const IN_MEMORY: Self = Self(NGX_HTTP_SUBREQUEST_IN_MEMORY as ngx_uint_t);
+    /// The subrequest is marked done even if it is not active when finalized.
+    pub const WAITED: Self = Self(NGX_HTTP_SUBREQUEST_WAITED as ngx_uint_t);
+    /// The subrequest is run in the location of the parent request, from the same phase.
+    pub const CLONE: Self = Self(NGX_HTTP_SUBREQUEST_CLONE as ngx_uint_t);
+    /// The subrequest runs independently of the parent request, which does not wait for it,
+    /// e.g. to mirror requests. Its response is discarded, unless [`Self::IN_MEMORY`] is set.
+    pub const BACKGROUND: Self = Self(NGX_HTTP_SUBREQUEST_BACKGROUND as ngx_uint_t);
+
+    /// Returns `true` if all flags of `other` are set.
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for SubrequestFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for SubrequestFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+type SubrequestDone = dyn FnOnce(&mut Request, Status);
+
+/// Completion closure of a subrequest, allocated from the parent request pool and passed as the
+/// post subrequest handler data.
+struct SubrequestHandler {
+    done: Cell<Option<Box<SubrequestDone>>>,
+}
+
+/// Builder for a [subrequest] with a Rust completion closure.
+///
+/// The parent request is resumed once the subrequest is finalized, e.g. by running its phase
+/// handler again, as done by the `auth_request` module:
+///
+/// ```rust,ignore
+/// // in an access phase handler, with `status: Rc<Cell<Option<HTTPStatus>>>` in the context
+/// if let Some(status) = ctx.status.get() {
+///     // the subrequest is done, and the phase handler is called again
+///     return if status == HTTPStatus::OK { Status::NGX_OK } else { HTTPStatus::FORBIDDEN.into() };
+/// }
+/// let result = ctx.status.clone();
+/// let sr = http::SubrequestBuilder::new("/auth")
+///     .add_flags(http::SubrequestFlags::IN_MEMORY | http::SubrequestFlags::WAITED)
+///     .spawn(request, move |sr, _rc| {
+///         // use the body ...
+///         let _user = sr.in_memory_body().map(<[u8]>::to_vec);
+///         result.set(Some(sr.status()));
+///     });
+/// if sr.is_none() {
+///     return Status::NGX_ERROR;
+/// }
+/// Status::NGX_AGAIN
+/// ```
+///
+/// [subrequest]: https://nginx.org/en/docs/dev/development_guide.html#http_subrequests
+pub struct SubrequestBuilder<'a> {
+    uri: &'a str,
+    args: Option<&'a str>,
+    method: Option<Method>,
+    flags: SubrequestFlags,
+    header_only: bool,
+    forward_body: bool,
+}
+
+impl<'a> SubrequestBuilder<'a> {
+    /// Creates a builder for a subrequest to `uri`.
+    pub fn new(uri: &'a str) -> Self {
+        SubrequestBuilder {
+            uri,
+            args: None,
+            method: None,
+            flags: SubrequestFlags::default(),
+            header_only: false,
+            forward_body: false,
+        }
+    }
+
+    /// Sets the query string of the subrequest, without the `?`.
+    pub fn args(mut self, args: &'a str) -> Self {
+        self.args = Some(args);
+        self
+    }
+
+    /// Sets the subrequest method, `GET` by default, or the method of the parent if
+    /// [`SubrequestBuilder::forward_body`] is set.
+    pub fn method(mut self, method: Method) -> Self {
+        self.method = Some(method);
+        self
+    }
+
+    /// Adds `flags` to the subrequest flags, none by default.
+    pub fn add_flags(mut self, flags: SubrequestFlags) -> Self {
+        self.flags |= flags;
+        self
+    }
+
+    /// Discards the response body of the subrequest, e.g. when only its status is checked.
+    pub fn header_only(mut self, header_only: bool) -> Self {
+        self.header_only = header_only;
+        self
+    }
+
+    /// Passes the request body of the parent to the subrequest, e.g. to mirror the request.
+    ///
+    /// Unless set with [`SubrequestBuilder::method`], the subrequest also has the method of the
+    /// parent. The body must already be read, e.g. with [`Request::read_body`], or the subrequest
+    /// is not created.
+    pub fn forward_body(mut self, forward_body: bool) -> Self {
+        self.forward_body = forward_body;
+        self
+    }
+
+    /// Creates the subrequest of `parent`, calling `done` with the subrequest and its final
+    /// status once it is finalized.
+    ///
+    /// Unless [`SubrequestBuilder::forward_body`] is set, the subrequest does not read the request
+    /// body of the client. Returns the subrequest, or `None` if it cannot be created, e.g. when the
+    /// subrequest limit is reached.
+    ///
+    /// A [`SubrequestFlags::BACKGROUND`] subrequest is not waited for: `parent` goes on with its
+    /// response, and `done` is only called with the final status of the subrequest, e.g. to log
+    /// the result of a mirrored request.
+    pub fn spawn<F>(self, parent: &mut Request, done: F) -> Option<&mut Request>
+    where
+        F: FnOnce(&mut Request, Status) + 'static,
+    {
+        let r: *mut ngx_http_request_t = parent.into();
+        let mut pool = parent.pool();
+
+        let handler = pool.allocate(SubrequestHandler {
+            done: Cell::new(Some(Box::new(done))),
+        });
+        let psr = pool.alloc_type::<ngx_http_post_subrequest_t>();
+        let body = if self.forward_body {
+            unsafe { (*r).request_body }
+        } else {
+            // Empty request body, so that the subrequest does not try to read the client body.
+            pool.calloc_type::<ngx_http_request_body_t>()
+        };
+        if handler.is_null() || psr.is_null() || body.is_null() {
+            return None;
+        }
+
+        unsafe {
+            (*psr).handler = Some(subrequest_handler);
+            (*psr).data = handler as *mut c_void;
+
+            let mut uri = pool.alloc_str(self.uri.as_bytes())?;
+            let mut args = match self.args {
+                Some(args) => Some(pool.alloc_str(args.as_bytes())?),
+                None => None,
+            };
+            let args = args.as_mut().map_or(ptr::null_mut(), |args| args as *mut _);
+            // Allocated first, as the subrequest cannot be cancelled once created.
+            let method = match self.method {
+                Some(method) => Some((pool.alloc_str(method.as_str().as_bytes())?, method)),
+                None => None,
+            };
+
+            let mut sr: *mut ngx_http_request_t = ptr::null_mut();
+            let rc = ngx_http_subrequest(r, &mut uri, args, &mut sr, psr, self.flags.0);
+            if rc != Status::NGX_OK.into() || sr.is_null() {
+                return None;
+            }
+
+            (*sr).request_body = body;
+            if let Some((method_name, method)) = method {
+                (*sr).method = method.to_ngx();
+                (*sr).method_name = method_name;
+            } else if self.forward_body {
+                // As the mirror module.
+                (*sr).method = (*r).method;
+                (*sr).method_name = (*r).method_name;
+            }
+            let background = self.flags.contains(SubrequestFlags::BACKGROUND);
+            if self.header_only || (background && !self.flags.contains(SubrequestFlags::IN_MEMORY)) {
+                (*sr).set_header_only(1);
+            }
+            Some(Request::from_ngx_http_request(sr))
+        }
+    }
+}
+
+unsafe extern "C" fn subrequest_handler(r: *mut ngx_http_request_t, data: *mut c_void, rc: ngx_int_t) -> ngx_int_t {
+    let handler = &*(data as *const SubrequestHandler);
+    // The handler is called each time the subrequest is finalized.
+    if let Some(done) = handler.done.take() {
+        done(Request::from_ngx_http_request(r), Status(rc));
+    }
+    rc
+}
+
+impl Request {
+    /// Parent request of a subrequest, or `None` for the main request.
+    pub fn parent(&mut self) -> Option<&mut Request> {
+        let r: *mut ngx_http_request_t = self.into();
+        unsafe {
+            if (*r).parent.is_null() {
+                return None;
+            }
+            Some(Request::from_ngx_http_request((*r).parent))
+        }
+    }
+
+    /// Response body of a subrequest created with [`SubrequestFlags::IN_MEMORY`].
+    ///
+    /// Returns `None` if there is no body, or if it was too large for the buffer.
+    pub fn in_memory_body(&self) -> Option<&[u8]> {
+        let r: *const ngx_http_request_t = self.into();
+        unsafe {
+            let out = (*r).out;
+            if (*r).subrequest_in_memory() == 0 || out.is_null() || (*out).buf.is_null() {
+                return None;
+            }
+            let b = (*out).buf;
+            if (*b).pos.is_null() {
+                return None;
+            }
+            Some(slice::from_raw_parts(
+                (*b).pos,
+                (*b).last.offset_from((*b).pos) as usize,
+            ))
+        }
+    }
+}
+
+/// Result of a subrequest issued with [`Request::fan_out`].
+#[derive(Clone, Debug, Default)]
+pub struct FanOutResult {
+    /// Response status, or `None` if the subrequest could not be created or did not complete
+    /// before the deadline.
+    pub status: Option<HTTPStatus>,
+    /// Response body, unless empty or larger than `subrequest_output_buffer_size`.
+    pub body: Option<Vec<u8>>,
+}
+
+type FanOutDone = dyn FnOnce(&mut Request, Vec<FanOutResult>) -> Status;
+
+/// State of [`Request::fan_out`], allocated from the request pool.
+struct FanOut {
+    request: *mut ngx_http_request_t,
+    results: RefCell<Vec<FanOutResult>>,
+    pending: Cell<usize>,
+    done: RefCell<Option<Box<FanOutDone>>>,
+    timer: RefCell<Option<Timer>>,
+}
+
+impl Request {
+    /// Issue `subrequests` concurrently, then call `done` with their results once all are
+    /// completed or `deadline` has passed.
+    ///
+    /// The subrequests are run in the background with their responses kept in memory, and the
+    /// results are in the order of `subrequests`. The request is finalized with the status
+    /// returned by `done`, which usually sends a response composed from the results. Subrequests
+    /// still running at the deadline are not aborted, but their results are discarded.
+    ///
+    /// This must be called from a content handler, which must return the returned status:
+    /// `NGX_DONE` once the subrequests are issued, or `NGX_ERROR`.
+    ///
+    /// ```rust,ignore
+    /// http_request_handler!(gateway_handler, |request: &mut http::Request| {
+    ///     let subrequests = vec![
+    ///         http::SubrequestBuilder::new("/users").args("id=1"),
+    ///         http::SubrequestBuilder::new("/orders").args("user=1"),
+    ///     ];
+    ///     request.fan_out(subrequests, Duration::from_secs(1), |request, results| {
+    ///         // compose and send the response ...
+    ///         Status::NGX_OK
+    ///     })
+    /// });
+    /// ```
+    pub fn fan_out<F>(&mut self, subrequests: Vec<SubrequestBuilder<'_>>, deadline: Duration, done: F) -> Status
+    where
+        F: FnOnce(&mut Request, Vec<FanOutResult>) -> Status + 'static,
+    {
+        let r: *mut ngx_http_request_t = self.into();
+        // SAFETY: the state is dropped with the request pool, which is shared by the subrequests
+        // and outlives them.
+        let state = self.pool().allocate(FanOut {
+            request: r,
+            results: RefCell::new(vec![FanOutResult::default(); subrequests.len()]),
+            pending: Cell::new(subrequests.len()),
+            done: RefCell::new(Some(Box::new(done))),
+            timer: RefCell::new(None),
+        });
+        if state.is_null() {
+            return Status::NGX_ERROR;
+        }
+
+        let mut timer = Timer::new(move || unsafe { fan_out_done(state) });
+        timer.set_log(self.log());
+        // The request is pending until the timer expires, so it must fire before the worker exits.
+        timer.set_cancelable(false);
+        timer.start(deadline);
+        // SAFETY: the request is kept alive until `done` is called, as the reference count keeps
+        // it from being finalized.
+        unsafe {
+            *(*state).timer.borrow_mut() = Some(timer);
+            let main = (*r).main;
+            (*main).set_count((*main).count() + 1);
+        }
+
+        for (i, sr) in subrequests.into_iter().enumerate() {
+            let sr = sr
+                .add_flags(SubrequestFlags::IN_MEMORY | SubrequestFlags::BACKGROUND)
+                .spawn(self, move |sr, rc| unsafe { fan_out_result(state, i, sr, rc) });
+            if sr.is_none() {
+                crate::ngx_log_error!(NGX_LOG_ERR, self.log(), "fan-out subrequest {} failed", i);
+                unsafe { fan_out_complete(state) };
+            }
+        }
+
+        unsafe {
+            if (*state).pending.get() == 0 {
+                fan_out_schedule_done(state);
+            }
+        }
+        Status::NGX_DONE
+    }
+}
+
+unsafe fn fan_out_result(state: *mut FanOut, i: usize, sr: &mut Request, rc: Status) {
+    if (*state).done.borrow().is_none() {
+        // The deadline has passed.
+        return;
+    }
+
+    // The status is not set if the subrequest failed before sending a response.
+    let status = match sr.status() {
+        HTTPStatus(0) if rc.0 >= NGX_HTTP_SPECIAL_RESPONSE as ngx_int_t => HTTPStatus(rc.0 as ngx_uint_t),
+        HTTPStatus(0) => HTTPStatus::INTERNAL_SERVER_ERROR,
+        status => status,
+    };
+    (*state).results.borrow_mut()[i] = FanOutResult {
+        status: Some(status),
+        body: sr.in_memory_body().map(<[u8]>::to_vec),
+    };
+
+    fan_out_complete(state);
+}
+
+unsafe fn fan_out_complete(state: *mut FanOut) {
+    let pending = (*state).pending.get() - 1;
+    (*state).pending.set(pending);
+    if pending == 0 {
+        fan_out_schedule_done(state);
+    }
+}
+
+/// Calls `done` from the event loop, rather than from the finalization of a subrequest.
+unsafe fn fan_out_schedule_done(state: *mut FanOut) {
+    if let Some(timer) = (*state).timer.borrow_mut().as_mut() {
+        // `ngx_add_timer` keeps an active timer expiring within `NGX_TIMER_LAZY_DELAY`, so the
+        // deadline is cancelled first to expire on the next event loop iteration.
+        timer.cancel();
+        timer.start(Duration::ZERO);
+    }
+}
+
+unsafe fn fan_out_done(state: *mut FanOut) {
+    let Some(done) = (*state).done.borrow_mut().take() else {
+        return;
+    };
+
+    let r = (*state).request;
+    let c = (*r).connection;
+    let rc = if (*c).error() != 0 {
+        Status::NGX_ERROR
+    } else {
+        let results = mem::take(&mut *(*state).results.borrow_mut());
+        done(Request::from_ngx_http_request(r), results)
+    };
+    ngx_http_finalize_request(r, rc.0);
+    ngx_http_run_posted_requests(c);
+}
@@ -0,0 +1,74 @@
+use crate::core::*;
+use crate::ffi::*;
+use crate::http::{HTTPStatus, Request};
+
+impl Request {
+    /// Run blocking `work` on the thread `pool`, then `complete` with its result on the event loop.
+    ///
+    /// The request is finalized with the status returned by `complete`, or with an internal
+    /// server error if `work` panicked. The pool is referenced at configuration time with
+    /// [`ThreadPool::add`], so NGINX checks that it exists, and stored in the module configuration.
+    ///
+    /// This must be called from a content handler, which must return the returned status:
+    /// `NGX_DONE` once the work is queued, or `NGX_ERROR`.
+    ///
+    /// ```rust,ignore
+    /// // in a directive handler
+    /// conf.thread_pool = ThreadPool::add(&mut *cf, "default");
+    ///
+    /// // in a content handler
+    /// http_request_handler!(hash_handler, |request: &mut http::Request| {
+    ///     let Some(pool) = module_conf(request).thread_pool else {
+    ///         return Status::NGX_DECLINED;
+    ///     };
+    ///     let data = load_data(request);
+    ///     request.spawn_blocking(pool, move || expensive_hash(&data), |request, hash| {
+    ///         request.add_header_out("X-Hash", &hash);
+    ///         // send the response ...
+    ///         Status::NGX_OK
+    ///     })
+    /// });
+    /// ```
+    pub fn spawn_blocking<F, R, C>(&mut self, pool: ThreadPool, work: F, complete: C) -> Status
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+        C: FnOnce(&mut Request, R) -> Status + 'static,
+    {
+        let r: *mut ngx_http_request_t = self.into();
+        // SAFETY: the request is kept alive until the work completes: the reference count keeps it
+        // from being finalized, and `blocked` from being freed if terminated.
+        unsafe {
+            let main = (*r).main;
+            (*main).set_count((*main).count() + 1);
+            (*main).set_blocked((*main).blocked() + 1);
+        }
+
+        let status = pool.post(self.log(), work, move |result| unsafe {
+            let main = (*r).main;
+            (*main).set_blocked((*main).blocked() - 1);
+
+            let c = (*r).connection;
+            let rc = if (*c).error() != 0 {
+                Status::NGX_ERROR
+            } else {
+                match result {
+                    Ok(value) => complete(Request::from_ngx_http_request(r), value),
+                    Err(_) => HTTPStatus::INTERNAL_SERVER_ERROR.into(),
+                }
+            };
+            ngx_http_finalize_request(r, rc.0);
+            ngx_http_run_posted_requests(c);
+        });
+
+        if status != Status::NGX_OK {
+            unsafe {
+                let main = (*r).main;
+                (*main).set_count((*main).count() - 1);
+                (*main).set_blocked((*main).blocked() - 1);
+            }
+            return Status::NGX_ERROR;
+        }
+        Status::NGX_DONE
+    }
+}
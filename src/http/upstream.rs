@@ -1,3 +1,17 @@
+use crate::core::*;
+use crate::ffi::*;
+use crate::http::{
+    merge_conf_value, ngx_http_conf_get_module_loc_conf, ngx_http_conf_get_module_srv_conf, set_location_handler,
+    DirectiveError, HttpHandler, Merge, MergeConfigError, Request,
+};
+
+use std::ffi::CString;
+use std::marker::PhantomData;
+use std::mem;
+use std::os::raw::c_void;
+use std::ptr::{self, NonNull};
+use std::time::Duration;
+
 /// Define a static upstream peer initializer
 ///
 /// Initializes the upstream 'get', 'free', and 'session' callbacks and gives the module writer an
@@ -19,3 +33,853 @@ macro_rules! http_upstream_init_peer_pt {
         }
     };
 }
+
+/// The `UpstreamProtocol` trait implements the protocol of a proxied server, e.g. memcached, for
+/// an [upstream] created with [`Request::upstream_create`].
+///
+/// Each request gets its own protocol value, which holds the parsing state and is dropped with the
+/// request pool. The callbacks mirror those of `ngx_http_upstream_t` and receive the request and
+/// its upstream:
+///
+/// * `create_request` builds the request to the server into `u.request_bufs`.
+/// * `process_header` parses the response header from `u.buffer`, setting `u.headers_in` and
+///   `u.length`, and returns `NGX_AGAIN` until the header is complete.
+/// * `input_filter` receives `bytes` more bytes of the response body, appended to `u.buffer`, and
+///   adds them to `u.out_bufs`.
+///
+/// The response body is not buffered, as with the memcached module. Response headers should be
+/// added to the request `headers_out` by `process_header`, not to `u.headers_in.headers`.
+///
+/// ```rust,ignore
+/// struct Echo;
+///
+/// impl UpstreamProtocol for Echo {
+///     const SCHEMA: &'static str = "echo://";
+///
+///     fn create_request(&mut self, request: &mut Request, u: &mut ngx_http_upstream_t) -> Status {
+///         // allocate a buffer with the query from the request pool into `u.request_bufs`
+///         Status::NGX_OK
+///     }
+///
+///     fn process_header(&mut self, request: &mut Request, u: &mut ngx_http_upstream_t) -> Status {
+///         u.headers_in.status_n = 200;
+///         unsafe { (*u.state).status = 200 };
+///         u.length = -1;
+///         Status::NGX_OK
+///     }
+/// }
+///
+/// http_request_handler!(echo_handler, |request: &mut Request| {
+///     let conf = request.get_module_loc_conf::<ModuleConfig>(unsafe { &*addr_of!(ngx_http_echo_module) });
+///     let conf = conf.expect("module config is none");
+///     if request.upstream_create(&conf.upstream, Echo).is_none() {
+///         return HTTPStatus::INTERNAL_SERVER_ERROR.into();
+///     }
+///     request.upstream_init()
+/// });
+/// ```
+///
+/// [upstream]: https://nginx.org/en/docs/dev/development_guide.html#http_load_balancing
+pub trait UpstreamProtocol: Sized + 'static {
+    /// Protocol prefix of the server address in logs, e.g. `memcached://`.
+    const SCHEMA: &'static str;
+
+    /// Creates the request to send to the server.
+    fn create_request(&mut self, request: &mut Request, u: &mut ngx_http_upstream_t) -> Status;
+
+    /// Resets the state before the request is retried with the next server.
+    fn reinit_request(&mut self, _request: &mut Request, _u: &mut ngx_http_upstream_t) -> Status {
+        Status::NGX_OK
+    }
+
+    /// Parses the response header received so far.
+    ///
+    /// Returns `NGX_AGAIN` if the header is incomplete, `NGX_HTTP_UPSTREAM_INVALID_HEADER` to try
+    /// the next server, or `NGX_OK` once the header is parsed.
+    fn process_header(&mut self, request: &mut Request, u: &mut ngx_http_upstream_t) -> Status;
+
+    /// Prepares for the response body, after the header is processed.
+    fn input_filter_init(&mut self, _request: &mut Request, _u: &mut ngx_http_upstream_t) -> Status {
+        Status::NGX_OK
+    }
+
+    /// Processes `bytes` bytes of the response body, received at the end of `u.buffer`.
+    ///
+    /// The default implementation passes the body unmodified, up to `u.length` bytes unless it is
+    /// `-1`.
+    fn input_filter(&mut self, request: &mut Request, u: &mut ngx_http_upstream_t, bytes: usize) -> Status {
+        upstream_non_buffered_filter(request, u, bytes)
+    }
+
+    /// Called if the request is aborted.
+    fn abort(&mut self, _request: &mut Request, _u: &mut ngx_http_upstream_t) {}
+
+    /// Called when the request to the server is finalized with `rc`.
+    fn finalize(&mut self, _request: &mut Request, _u: &mut ngx_http_upstream_t, _rc: ngx_int_t) {}
+}
+
+/// Append the new bytes of `u.buffer` to `u.out_bufs`, as `ngx_http_upstream_non_buffered_filter`.
+fn upstream_non_buffered_filter(request: &mut Request, u: &mut ngx_http_upstream_t, bytes: usize) -> Status {
+    if u.length == 0 {
+        crate::ngx_log_error!(
+            NGX_LOG_WARN,
+            request.log(),
+            "upstream sent more data than specified in \"Content-Length\" header"
+        );
+        return Status::NGX_OK;
+    }
+
+    let r: *mut ngx_http_request_t = request.into();
+    let buf = unsafe {
+        let mut ll: *mut *mut ngx_chain_t = &mut u.out_bufs;
+        while !(*ll).is_null() {
+            ll = &mut (**ll).next;
+        }
+
+        let cl = ngx_chain_get_free_buf((*r).pool, &mut u.free_bufs);
+        if cl.is_null() {
+            return Status::NGX_ERROR;
+        }
+        *ll = cl;
+
+        let buf = (*cl).buf;
+        (*buf).set_flush(1);
+        (*buf).set_memory(1);
+        (*buf).pos = u.buffer.last;
+        u.buffer.last = u.buffer.last.add(bytes);
+        (*buf).last = u.buffer.last;
+        (*buf).tag = u.output.tag;
+        buf
+    };
+
+    if u.length == -1 {
+        return Status::NGX_OK;
+    }
+
+    if bytes as off_t > u.length {
+        crate::ngx_log_error!(
+            NGX_LOG_WARN,
+            request.log(),
+            "upstream sent more data than specified in \"Content-Length\" header"
+        );
+        unsafe { (*buf).last = (*buf).pos.add(u.length as usize) };
+        u.length = 0;
+        return Status::NGX_OK;
+    }
+
+    u.length -= bytes as off_t;
+    Status::NGX_OK
+}
+
+/// Per-request state of an upstream created with [`Request::upstream_create`], stored as the
+/// upstream `input_filter_ctx`.
+struct UpstreamState<P> {
+    request: *mut ngx_http_request_t,
+    protocol: P,
+}
+
+unsafe fn upstream_state<'a, P>(
+    r: *mut ngx_http_request_t,
+) -> (&'a mut P, &'a mut Request, &'a mut ngx_http_upstream_t) {
+    let u = (*r).upstream;
+    let state = (*u).input_filter_ctx as *mut UpstreamState<P>;
+    (&mut (*state).protocol, Request::from_ngx_http_request(r), &mut *u)
+}
+
+unsafe extern "C" fn upstream_create_request<P: UpstreamProtocol>(r: *mut ngx_http_request_t) -> ngx_int_t {
+    let (protocol, request, u) = upstream_state::<P>(r);
+    protocol.create_request(request, u).into()
+}
+
+unsafe extern "C" fn upstream_reinit_request<P: UpstreamProtocol>(r: *mut ngx_http_request_t) -> ngx_int_t {
+    let (protocol, request, u) = upstream_state::<P>(r);
+    protocol.reinit_request(request, u).into()
+}
+
+unsafe extern "C" fn upstream_process_header<P: UpstreamProtocol>(r: *mut ngx_http_request_t) -> ngx_int_t {
+    let (protocol, request, u) = upstream_state::<P>(r);
+    protocol.process_header(request, u).into()
+}
+
+unsafe extern "C" fn upstream_input_filter_init<P: UpstreamProtocol>(data: *mut c_void) -> ngx_int_t {
+    let (protocol, request, u) = upstream_state::<P>((*(data as *mut UpstreamState<P>)).request);
+    protocol.input_filter_init(request, u).into()
+}
+
+unsafe extern "C" fn upstream_input_filter<P: UpstreamProtocol>(data: *mut c_void, bytes: ssize_t) -> ngx_int_t {
+    let (protocol, request, u) = upstream_state::<P>((*(data as *mut UpstreamState<P>)).request);
+    protocol.input_filter(request, u, bytes as usize).into()
+}
+
+unsafe extern "C" fn upstream_abort_request<P: UpstreamProtocol>(r: *mut ngx_http_request_t) {
+    let (protocol, request, u) = upstream_state::<P>(r);
+    protocol.abort(request, u)
+}
+
+unsafe extern "C" fn upstream_finalize_request<P: UpstreamProtocol>(r: *mut ngx_http_request_t, rc: ngx_int_t) {
+    let (protocol, request, u) = upstream_state::<P>(r);
+    protocol.finalize(request, u, rc)
+}
+
+impl Request {
+    /// Creates the upstream of the request, talking to the servers of `conf` with `protocol`.
+    ///
+    /// Returns the upstream to adjust before [`Request::upstream_init`], or `None` on allocation
+    /// failure. The request body should be read or discarded before.
+    pub fn upstream_create<P: UpstreamProtocol>(
+        &mut self,
+        conf: &UpstreamConf,
+        protocol: P,
+    ) -> Option<&mut ngx_http_upstream_t> {
+        let r: *mut ngx_http_request_t = self.into();
+        unsafe {
+            if ngx_http_upstream_create(r) != Status::NGX_OK.into() {
+                return None;
+            }
+
+            let state = self.pool().allocate(UpstreamState { request: r, protocol });
+            if state.is_null() {
+                return None;
+            }
+
+            let u = &mut *(*r).upstream;
+            u.schema = ngx_str_t {
+                len: P::SCHEMA.len(),
+                data: P::SCHEMA.as_ptr() as *mut u_char,
+            };
+            // Only identifies the buffers of this upstream.
+            u.output.tag = state as ngx_buf_tag_t;
+            // The configuration is not modified by the upstream.
+            u.conf = &conf.conf as *const ngx_http_upstream_conf_t as *mut ngx_http_upstream_conf_t;
+
+            u.create_request = Some(upstream_create_request::<P>);
+            u.reinit_request = Some(upstream_reinit_request::<P>);
+            u.process_header = Some(upstream_process_header::<P>);
+            u.abort_request = Some(upstream_abort_request::<P>);
+            u.finalize_request = Some(upstream_finalize_request::<P>);
+            u.input_filter_init = Some(upstream_input_filter_init::<P>);
+            u.input_filter = Some(upstream_input_filter::<P>);
+            u.input_filter_ctx = state as *mut c_void;
+
+            Some(u)
+        }
+    }
+
+    /// Starts the upstream created with [`Request::upstream_create`].
+    ///
+    /// This must be called from a content handler, which must return the returned `NGX_DONE`
+    /// status: the request is finalized once the response is sent.
+    pub fn upstream_init(&mut self) -> Status {
+        let r: *mut ngx_http_request_t = self.into();
+        unsafe {
+            let main = (*r).main;
+            (*main).set_count((*main).count() + 1);
+            ngx_http_upstream_init(r);
+        }
+        Status::NGX_DONE
+    }
+}
+
+/// Upstream settings of a location, the typed counterpart of `ngx_http_upstream_conf_t`.
+///
+/// Unset settings take the value of the previous level, or the defaults of the memcached module,
+/// once the configuration is merged. The enclosing module configuration must thus call
+/// [`Merge::merge`] from its own implementation.
+pub struct UpstreamConf {
+    /// Servers to pass requests to, set with [`UpstreamConf::set_pass`].
+    pub upstream: Option<NonNull<ngx_http_upstream_srv_conf_t>>,
+    /// Timeout for establishing a connection, 60 seconds by default.
+    pub connect_timeout: Option<Duration>,
+    /// Timeout between two write operations, 60 seconds by default.
+    pub send_timeout: Option<Duration>,
+    /// Timeout between two read operations, 60 seconds by default.
+    pub read_timeout: Option<Duration>,
+    /// Size of the buffer for the response, one memory page by default.
+    pub buffer_size: Option<usize>,
+    /// Number of attempts to pass a request to the next server, unlimited (0) by default.
+    pub next_upstream_tries: Option<ngx_uint_t>,
+    /// Time allowed to pass a request to the next server, unlimited (0) by default.
+    pub next_upstream_timeout: Option<Duration>,
+    conf: ngx_http_upstream_conf_t,
+}
+
+impl Default for UpstreamConf {
+    fn default() -> Self {
+        UpstreamConf {
+            upstream: None,
+            connect_timeout: None,
+            send_timeout: None,
+            read_timeout: None,
+            buffer_size: None,
+            next_upstream_tries: None,
+            next_upstream_timeout: None,
+            // SAFETY: all-zero is a valid `ngx_http_upstream_conf_t`, disabling most features.
+            conf: unsafe { mem::zeroed() },
+        }
+    }
+}
+
+impl UpstreamConf {
+    /// Sets the servers from the address of a `*_pass` directive, either a `host:port` pair or the
+    /// name of an `upstream` block, and `handler` as the location content handler.
+    ///
+    /// This must be called from a directive handler in the `location` context.
+    pub fn set_pass(&mut self, cf: &mut ngx_conf_t, url: &NgxStr, handler: HttpHandler) -> Result<(), DirectiveError> {
+        if self.upstream.is_some() {
+            return Err(DirectiveError::Duplicate);
+        }
+
+        let bytes = url.as_bytes();
+        unsafe {
+            // `ngx_http_upstream_add` keeps pointers to the address.
+            let data = Pool::from_ngx_pool(cf.pool).alloc(bytes.len()) as *mut u_char;
+            if data.is_null() {
+                return Err(DirectiveError::Message("memory allocation failed".to_string()));
+            }
+            ptr::copy_nonoverlapping(bytes.as_ptr(), data, bytes.len());
+
+            let mut u: ngx_url_t = mem::zeroed();
+            u.url = ngx_str_t { len: bytes.len(), data };
+            u.set_no_resolve(1);
+
+            let uscf = ngx_http_upstream_add(cf, &mut u, 0);
+            self.upstream =
+                Some(NonNull::new(uscf).ok_or_else(|| DirectiveError::InvalidValue(url.to_string_lossy().into()))?);
+
+            set_location_handler(cf, handler);
+
+            let clcf = ngx_http_conf_get_module_loc_conf(cf, &*ptr::addr_of!(ngx_http_core_module));
+            if (*clcf).name.len > 0 && *(*clcf).name.data.add((*clcf).name.len - 1) == b'/' {
+                (*clcf).set_auto_redirect(1);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the merged `ngx_http_upstream_conf_t`.
+    pub fn as_ngx_upstream_conf(&self) -> &ngx_http_upstream_conf_t {
+        &self.conf
+    }
+}
+
+impl Merge for UpstreamConf {
+    fn merge(&mut self, prev: &UpstreamConf) -> Result<(), MergeConfigError> {
+        if self.upstream.is_none() {
+            self.upstream = prev.upstream;
+        }
+        merge_conf_value(
+            &mut self.connect_timeout,
+            &prev.connect_timeout,
+            Duration::from_secs(60),
+        );
+        merge_conf_value(&mut self.send_timeout, &prev.send_timeout, Duration::from_secs(60));
+        merge_conf_value(&mut self.read_timeout, &prev.read_timeout, Duration::from_secs(60));
+        merge_conf_value(&mut self.buffer_size, &prev.buffer_size, unsafe { ngx_pagesize });
+        merge_conf_value(&mut self.next_upstream_tries, &prev.next_upstream_tries, 0);
+        merge_conf_value(
+            &mut self.next_upstream_timeout,
+            &prev.next_upstream_timeout,
+            Duration::ZERO,
+        );
+
+        let conf = &mut self.conf;
+        conf.upstream = self.upstream.map_or(ptr::null_mut(), NonNull::as_ptr);
+        conf.connect_timeout = duration_to_msec(self.connect_timeout.unwrap_or_default());
+        conf.send_timeout = duration_to_msec(self.send_timeout.unwrap_or_default());
+        conf.read_timeout = duration_to_msec(self.read_timeout.unwrap_or_default());
+        conf.next_upstream_timeout = duration_to_msec(self.next_upstream_timeout.unwrap_or_default());
+        conf.buffer_size = self.buffer_size.unwrap_or_default();
+        conf.next_upstream_tries = self.next_upstream_tries.unwrap_or_default();
+        conf.next_upstream =
+            (NGX_CONF_BITMASK_SET | NGX_HTTP_UPSTREAM_FT_ERROR | NGX_HTTP_UPSTREAM_FT_TIMEOUT) as ngx_uint_t;
+        // The settings hardcoded by the memcached module.
+        conf.intercept_errors = 1;
+        conf.set_intercept_404(1);
+        conf.force_ranges = 1;
+        Ok(())
+    }
+}
+
+/// The `LoadBalancer` trait implements a [load balancing] method for an `upstream` block, such as
+/// least connections or consistent hashing.
+///
+/// Balancers build on the round-robin method: the upstream peers are initialized by
+/// `ngx_http_upstream_init_round_robin`, and can be inspected with [`UpstreamSrvConf::peers`] and
+/// [`RoundRobin::peers`]. Each request gets its own [`LoadBalancer::PeerData`], created by
+/// [`LoadBalancer::init_peer`] and dropped with the request pool.
+///
+/// The balancer is enabled for an `upstream` block with [`set_load_balancer`], usually from a
+/// directive handler.
+///
+/// ```rust,ignore
+/// struct LeastConn;
+///
+/// impl LoadBalancer for LeastConn {
+///     type PeerData = RoundRobin;
+///
+///     fn init_peer(_request: &mut Request, _us: &mut UpstreamSrvConf, rr: RoundRobin) -> Option<RoundRobin> {
+///         Some(rr)
+///     }
+///
+///     fn get(pc: &mut PeerConnection, rr: &mut RoundRobin) -> Status {
+///         let peers = rr.peers();
+///         let best = peers
+///             .iter()
+///             .enumerate()
+///             .filter(|(i, peer)| peer.is_available() && !rr.is_tried(*i))
+///             .min_by_key(|(_, peer)| peer.conns() * 1000 / peer.weight().max(1) as usize);
+///         match best {
+///             Some((i, _)) => rr.select(&peers, pc, i),
+///             None => Status::NGX_BUSY,
+///         }
+///     }
+///
+///     fn free(pc: &mut PeerConnection, rr: &mut RoundRobin, state: ngx_uint_t) {
+///         rr.free(pc, state)
+///     }
+/// }
+/// ```
+///
+/// [load balancing]: https://nginx.org/en/docs/dev/development_guide.html#http_load_balancing
+pub trait LoadBalancer: 'static {
+    /// Per-request state of the balancer.
+    type PeerData: 'static;
+
+    /// Initializes the upstream at configuration time, once the round-robin peers are created.
+    fn init_upstream(_cf: &mut ngx_conf_t, _us: &mut UpstreamSrvConf) -> Status {
+        Status::NGX_OK
+    }
+
+    /// Creates the state of the balancer for a request, with the round-robin state of the request.
+    ///
+    /// Returns `None` to fail the request.
+    fn init_peer(request: &mut Request, us: &mut UpstreamSrvConf, rr: RoundRobin) -> Option<Self::PeerData>;
+
+    /// Selects the peer for a connection attempt, e.g. with [`RoundRobin::select`].
+    ///
+    /// Returns `NGX_OK` once a peer is selected, or `NGX_BUSY` if no peer is available.
+    fn get(pc: &mut PeerConnection, data: &mut Self::PeerData) -> Status;
+
+    /// Releases the peer after a connection attempt, with the `NGX_PEER_FAILED` flag in `state`
+    /// if the attempt failed.
+    ///
+    /// Peers selected with [`RoundRobin::select`] must be released with [`RoundRobin::free`].
+    fn free(pc: &mut PeerConnection, data: &mut Self::PeerData, state: ngx_uint_t);
+}
+
+/// Use the balancer `B` for the `upstream` block being parsed.
+///
+/// This must be called from a directive handler in the `upstream` context. Servers of the block
+/// may set the `weight`, `max_conns`, `max_fails`, `fail_timeout` and `down` parameters.
+pub fn set_load_balancer<B: LoadBalancer>(cf: &mut ngx_conf_t) {
+    // SAFETY: `cf` is an `upstream` block configuration, where `ngx_http_upstream_module` server
+    // configuration is always present.
+    unsafe {
+        let uscf = ngx_http_conf_get_module_srv_conf(cf, &*ptr::addr_of!(ngx_http_upstream_module))
+            as *mut ngx_http_upstream_srv_conf_t;
+
+        if (*uscf).peer.init_upstream.is_some() {
+            let message = CString::new("load balancing method redefined").unwrap();
+            ngx_conf_log_error(NGX_LOG_WARN as ngx_uint_t, cf, 0, message.as_ptr());
+        }
+
+        (*uscf).peer.init_upstream = Some(load_balancer_init_upstream::<B>);
+        (*uscf).flags = (NGX_HTTP_UPSTREAM_CREATE
+            | NGX_HTTP_UPSTREAM_WEIGHT
+            | NGX_HTTP_UPSTREAM_MAX_CONNS
+            | NGX_HTTP_UPSTREAM_MAX_FAILS
+            | NGX_HTTP_UPSTREAM_FAIL_TIMEOUT
+            | NGX_HTTP_UPSTREAM_DOWN) as ngx_uint_t;
+    }
+}
+
+/// Per-request state of a [`LoadBalancer`], stored as the upstream peer data.
+///
+/// The round-robin state comes first, as for the nginx hash module, as it is also passed to the
+/// round-robin SSL session callbacks.
+#[repr(C)]
+struct LoadBalancerState<T> {
+    rrp: ngx_http_upstream_rr_peer_data_t,
+    data: Option<T>,
+}
+
+unsafe extern "C" fn load_balancer_init_upstream<B: LoadBalancer>(
+    cf: *mut ngx_conf_t,
+    us: *mut ngx_http_upstream_srv_conf_t,
+) -> ngx_int_t {
+    if ngx_http_upstream_init_round_robin(cf, us) != Status::NGX_OK.into() {
+        return Status::NGX_ERROR.into();
+    }
+    (*us).peer.init = Some(load_balancer_init_peer::<B>);
+    B::init_upstream(&mut *cf, UpstreamSrvConf::from_ngx_upstream_srv_conf(us)).into()
+}
+
+unsafe extern "C" fn load_balancer_init_peer<B: LoadBalancer>(
+    r: *mut ngx_http_request_t,
+    us: *mut ngx_http_upstream_srv_conf_t,
+) -> ngx_int_t {
+    if ngx_http_upstream_init_round_robin_peer(r, us) != Status::NGX_OK.into() {
+        return Status::NGX_ERROR.into();
+    }
+
+    let request = Request::from_ngx_http_request(r);
+    let state = request.pool().allocate(LoadBalancerState::<B::PeerData> {
+        rrp: mem::zeroed(),
+        data: None,
+    });
+    if state.is_null() {
+        return Status::NGX_ERROR.into();
+    }
+
+    // Move the round-robin state to the balancer state, including the inline bitmap of tried
+    // peers.
+    let u = (*r).upstream;
+    let rrp = (*u).peer.data as *mut ngx_http_upstream_rr_peer_data_t;
+    (*state).rrp = *rrp;
+    if ptr::eq((*rrp).tried, &(*rrp).data) {
+        (*state).rrp.tried = &mut (*state).rrp.data;
+    }
+
+    let rr = RoundRobin(NonNull::from(&mut (*state).rrp));
+    let Some(data) = B::init_peer(request, UpstreamSrvConf::from_ngx_upstream_srv_conf(us), rr) else {
+        return Status::NGX_ERROR.into();
+    };
+    (*state).data = Some(data);
+
+    (*u).peer.data = state as *mut c_void;
+    (*u).peer.get = Some(load_balancer_get_peer::<B>);
+    (*u).peer.free = Some(load_balancer_free_peer::<B>);
+    Status::NGX_OK.into()
+}
+
+unsafe extern "C" fn load_balancer_get_peer<B: LoadBalancer>(
+    pc: *mut ngx_peer_connection_t,
+    data: *mut c_void,
+) -> ngx_int_t {
+    let state = &mut *(data as *mut LoadBalancerState<B::PeerData>);
+    match state.data.as_mut() {
+        Some(data) => B::get(PeerConnection::from_ngx_peer_connection(pc), data).into(),
+        None => Status::NGX_ERROR.into(),
+    }
+}
+
+unsafe extern "C" fn load_balancer_free_peer<B: LoadBalancer>(
+    pc: *mut ngx_peer_connection_t,
+    data: *mut c_void,
+    state: ngx_uint_t,
+) {
+    let lb = &mut *(data as *mut LoadBalancerState<B::PeerData>);
+    if let Some(data) = lb.data.as_mut() {
+        B::free(PeerConnection::from_ngx_peer_connection(pc), data, state);
+    }
+}
+
+/// Wrapper struct for an [`ngx_http_upstream_srv_conf_t`], the configuration of an `upstream`
+/// block.
+///
+/// [`ngx_http_upstream_srv_conf_t`]: https://nginx.org/en/docs/dev/development_guide.html#http_load_balancing
+#[repr(transparent)]
+pub struct UpstreamSrvConf(ngx_http_upstream_srv_conf_t);
+
+impl UpstreamSrvConf {
+    /// Create an [`UpstreamSrvConf`] from an [`ngx_http_upstream_srv_conf_t`].
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null pointer to a valid `ngx_http_upstream_srv_conf_t`.
+    pub unsafe fn from_ngx_upstream_srv_conf<'a>(us: *mut ngx_http_upstream_srv_conf_t) -> &'a mut UpstreamSrvConf {
+        &mut *us.cast::<UpstreamSrvConf>()
+    }
+
+    /// Name of the `upstream` block, or address of an implicit upstream.
+    pub fn host(&self) -> &NgxStr {
+        unsafe { NgxStr::from_ngx_str(self.0.host) }
+    }
+
+    /// Module server configuration of the `upstream` block.
+    pub fn get_module_srv_conf<T>(&self, module: &ngx_module_t) -> Option<&T> {
+        let conf = unsafe { *self.0.srv_conf.add(module.ctx_index) } as *const T;
+        unsafe { conf.as_ref() }
+    }
+
+    /// Mutable module server configuration of the `upstream` block.
+    pub fn get_module_srv_conf_mut<T>(&mut self, module: &ngx_module_t) -> Option<&mut T> {
+        let conf = unsafe { *self.0.srv_conf.add(module.ctx_index) } as *mut T;
+        unsafe { conf.as_mut() }
+    }
+
+    /// Round-robin peers of the upstream, once initialized.
+    pub fn peers(&self) -> Option<RoundRobinPeers<'_>> {
+        let peers = NonNull::new(self.0.peer.data as *mut ngx_http_upstream_rr_peers_t)?;
+        Some(unsafe { RoundRobinPeers::lock(peers) })
+    }
+
+    /// Returns the underlying `ngx_http_upstream_srv_conf_t` pointer.
+    pub fn as_ngx_upstream_srv_conf(&mut self) -> *mut ngx_http_upstream_srv_conf_t {
+        &mut self.0
+    }
+}
+
+/// Wrapper struct for an [`ngx_peer_connection_t`], a connection attempt to an upstream peer.
+///
+/// [`ngx_peer_connection_t`]: https://nginx.org/en/docs/dev/development_guide.html#http_load_balancing
+#[repr(transparent)]
+pub struct PeerConnection(ngx_peer_connection_t);
+
+impl PeerConnection {
+    /// Create a [`PeerConnection`] from an [`ngx_peer_connection_t`].
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null pointer to a valid `ngx_peer_connection_t`.
+    pub unsafe fn from_ngx_peer_connection<'a>(pc: *mut ngx_peer_connection_t) -> &'a mut PeerConnection {
+        &mut *pc.cast::<PeerConnection>()
+    }
+
+    /// Number of attempts left to connect to a peer, including this one.
+    pub fn tries(&self) -> ngx_uint_t {
+        self.0.tries
+    }
+
+    /// Name of the selected peer, if any.
+    pub fn name(&self) -> Option<&NgxStr> {
+        unsafe { self.0.name.as_ref().map(|name| NgxStr::from_ngx_str(*name)) }
+    }
+
+    /// Pointer to the [`ngx_log_t`] of the connection attempt.
+    ///
+    /// [`ngx_log_t`]: https://nginx.org/en/docs/dev/development_guide.html#logging
+    pub fn log(&self) -> *mut ngx_log_t {
+        self.0.log
+    }
+
+    /// Returns the underlying `ngx_peer_connection_t` pointer.
+    pub fn as_ngx_peer_connection(&mut self) -> *mut ngx_peer_connection_t {
+        &mut self.0
+    }
+}
+
+/// Round-robin state of a request, the `ngx_http_upstream_rr_peer_data_t` of the upstream.
+pub struct RoundRobin(NonNull<ngx_http_upstream_rr_peer_data_t>);
+
+impl RoundRobin {
+    /// Peers to select from, locked for exclusive access while the view is alive.
+    pub fn peers(&self) -> RoundRobinPeers<'_> {
+        unsafe { RoundRobinPeers::lock(NonNull::new_unchecked((*self.0.as_ptr()).peers)) }
+    }
+
+    /// Returns `true` if the peer at `index` was already tried for the request.
+    pub fn is_tried(&self, index: usize) -> bool {
+        let (n, m) = tried_bit(index);
+        unsafe { *(*self.0.as_ptr()).tried.add(n) & m != 0 }
+    }
+
+    /// Selects the peer at `index` of `peers` for the connection attempt, as the nginx balancers
+    /// do, and marks it as tried.
+    ///
+    /// Returns `NGX_ERROR` if `peers` are not the peers of the request, or `index` is out of
+    /// bounds.
+    pub fn select(&self, peers: &RoundRobinPeers<'_>, pc: &mut PeerConnection, index: usize) -> Status {
+        let rrp = self.0.as_ptr();
+        let Some(peer) = peers.peer_ptr(index) else {
+            return Status::NGX_ERROR;
+        };
+        // SAFETY: the peers are locked by `peers`.
+        unsafe {
+            if !ptr::eq((*rrp).peers, peers.peers.as_ptr()) {
+                return Status::NGX_ERROR;
+            }
+
+            let now = (*ngx_cached_time).sec;
+            if now - (*peer).checked > (*peer).fail_timeout {
+                (*peer).checked = now;
+            }
+
+            pc.0.set_cached(0);
+            pc.0.connection = ptr::null_mut();
+            pc.0.sockaddr = (*peer).sockaddr;
+            pc.0.socklen = (*peer).socklen;
+            pc.0.name = &mut (*peer).name;
+
+            (*peer).conns += 1;
+            (*rrp).current = peer;
+
+            let (n, m) = tried_bit(index);
+            *(*rrp).tried.add(n) |= m;
+        }
+        Status::NGX_OK
+    }
+
+    /// Selects a peer with the round-robin method.
+    pub fn get(&mut self, pc: &mut PeerConnection) -> Status {
+        Status(unsafe { ngx_http_upstream_get_round_robin_peer(&mut pc.0, self.0.as_ptr() as *mut c_void) })
+    }
+
+    /// Releases the selected peer, recording a failure if `state` has the `NGX_PEER_FAILED` flag.
+    pub fn free(&mut self, pc: &mut PeerConnection, state: ngx_uint_t) {
+        unsafe { ngx_http_upstream_free_round_robin_peer(&mut pc.0, self.0.as_ptr() as *mut c_void, state) }
+    }
+}
+
+fn tried_bit(index: usize) -> (usize, uintptr_t) {
+    let bits = 8 * mem::size_of::<uintptr_t>();
+    (index / bits, 1 << (index % bits))
+}
+
+/// Locked view of round-robin peers, an `ngx_http_upstream_rr_peers_t`.
+///
+/// When the upstream has a shared memory `zone`, the peers are shared by worker processes and
+/// locked until the view is dropped. The view must thus not be held while calling
+/// [`RoundRobin::get`] or [`RoundRobin::free`].
+pub struct RoundRobinPeers<'a> {
+    peers: NonNull<ngx_http_upstream_rr_peers_t>,
+    _marker: PhantomData<&'a ngx_http_upstream_rr_peers_t>,
+}
+
+impl RoundRobinPeers<'_> {
+    unsafe fn lock<'a>(peers: NonNull<ngx_http_upstream_rr_peers_t>) -> RoundRobinPeers<'a> {
+        let p = peers.as_ptr();
+        if !(*p).shpool.is_null() {
+            ngx_rwlock_wlock(&mut (*p).rwlock);
+        }
+        RoundRobinPeers {
+            peers,
+            _marker: PhantomData,
+        }
+    }
+
+    fn as_ref(&self) -> &ngx_http_upstream_rr_peers_t {
+        unsafe { self.peers.as_ref() }
+    }
+
+    /// Name of the upstream.
+    pub fn name(&self) -> Option<&NgxStr> {
+        unsafe { self.as_ref().name.as_ref().map(|name| NgxStr::from_ngx_str(*name)) }
+    }
+
+    /// Number of peers.
+    pub fn len(&self) -> usize {
+        self.as_ref().number
+    }
+
+    /// Returns `true` if there are no peers.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Sum of the weights of the peers.
+    pub fn total_weight(&self) -> ngx_uint_t {
+        self.as_ref().total_weight
+    }
+
+    /// Returns `true` if the peers have different weights.
+    pub fn is_weighted(&self) -> bool {
+        self.as_ref().weighted() != 0
+    }
+
+    /// Peer at `index`.
+    pub fn get(&self, index: usize) -> Option<&RoundRobinPeer> {
+        self.iter().nth(index)
+    }
+
+    fn peer_ptr(&self, index: usize) -> Option<*mut ngx_http_upstream_rr_peer_t> {
+        let mut peer = self.as_ref().peer;
+        for _ in 0..index {
+            if peer.is_null() {
+                return None;
+            }
+            peer = unsafe { (*peer).next };
+        }
+        (!peer.is_null()).then_some(peer)
+    }
+
+    /// Iterates over the peers.
+    pub fn iter(&self) -> impl Iterator<Item = &RoundRobinPeer> {
+        let mut peer = self.as_ref().peer;
+        std::iter::from_fn(move || {
+            let current = unsafe { peer.cast::<RoundRobinPeer>().as_ref()? };
+            peer = current.0.next;
+            Some(current)
+        })
+    }
+}
+
+impl Drop for RoundRobinPeers<'_> {
+    fn drop(&mut self) {
+        let p = self.peers.as_ptr();
+        unsafe {
+            if !(*p).shpool.is_null() {
+                ngx_rwlock_unlock(&mut (*p).rwlock);
+            }
+        }
+    }
+}
+
+/// A round-robin peer, an `ngx_http_upstream_rr_peer_t`.
+#[repr(transparent)]
+pub struct RoundRobinPeer(ngx_http_upstream_rr_peer_t);
+
+impl RoundRobinPeer {
+    /// Address of the peer.
+    pub fn name(&self) -> &NgxStr {
+        unsafe { NgxStr::from_ngx_str(self.0.name) }
+    }
+
+    /// Address of the server the peer is resolved from, as in the configuration.
+    pub fn server(&self) -> &NgxStr {
+        unsafe { NgxStr::from_ngx_str(self.0.server) }
+    }
+
+    /// Configured weight.
+    pub fn weight(&self) -> ngx_int_t {
+        self.0.weight
+    }
+
+    /// Weight reduced after failures, recovering over time.
+    pub fn effective_weight(&self) -> ngx_int_t {
+        self.0.effective_weight
+    }
+
+    /// Number of active connections.
+    pub fn conns(&self) -> ngx_uint_t {
+        self.0.conns
+    }
+
+    /// Maximum number of active connections, or 0 if unlimited.
+    pub fn max_conns(&self) -> ngx_uint_t {
+        self.0.max_conns
+    }
+
+    /// Number of failed attempts within `fail_timeout`.
+    pub fn fails(&self) -> ngx_uint_t {
+        self.0.fails
+    }
+
+    /// Number of failed attempts after which the peer is unavailable, or 0 if unlimited.
+    pub fn max_fails(&self) -> ngx_uint_t {
+        self.0.max_fails
+    }
+
+    /// Time the peer is unavailable after `max_fails` failures.
+    pub fn fail_timeout(&self) -> Duration {
+        Duration::from_secs(self.0.fail_timeout.max(0) as u64)
+    }
+
+    /// Returns `true` if the server is marked `down`.
+    pub fn is_down(&self) -> bool {
+        self.0.down != 0
+    }
+
+    /// Returns `true` if the peer can be selected: it is not down, failed or at `max_conns`.
+    pub fn is_available(&self) -> bool {
+        let peer = &self.0;
+        if peer.down != 0 {
+            return false;
+        }
+        let now = unsafe { (*ngx_cached_time).sec };
+        if peer.max_fails != 0 && peer.fails >= peer.max_fails && now - peer.checked <= peer.fail_timeout {
+            return false;
+        }
+        peer.max_conns == 0 || peer.conns < peer.max_conns
+    }
+}
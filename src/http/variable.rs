@@ -0,0 +1,400 @@
+use crate::core::*;
+use crate::ffi::*;
+use crate::http::Request;
+use crate::ngx_null_string;
+
+use std::ops::{BitOr, BitOrAssign};
+use std::ptr;
+
+/// Flags of a variable registered with [`VariableBuilder`].
+///
+/// See https://nginx.org/en/docs/dev/development_guide.html#http_variables for details.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct VariableFlags(pub ngx_uint_t);
+
+impl VariableFlags {
+    /// The variable can be redefined, e.g. with the `set` directive.
+    pub const CHANGEABLE: Self = Self(NGX_HTTP_VAR_CHANGEABLE as ngx_uint_t);
+    /// The value is not cached, the getter is called every time the variable is evaluated.
+    pub const NOCACHEABLE: Self = Self(NGX_HTTP_VAR_NOCACHEABLE as ngx_uint_t);
+    /// The variable is only accessed by index, not by name.
+    pub const NOHASH: Self = Self(NGX_HTTP_VAR_NOHASH as ngx_uint_t);
+    /// A variable with the same name defined by another module takes precedence.
+    pub const WEAK: Self = Self(NGX_HTTP_VAR_WEAK as ngx_uint_t);
+    /// The name is a prefix; the variable matches all names starting with it.
+    ///
+    /// This flag is set by [`VariableBuilder::prefix_getter`].
+    pub const PREFIX: Self = Self(NGX_HTTP_VAR_PREFIX as ngx_uint_t);
+
+    /// Returns `true` if all flags of `other` are set.
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for VariableFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for VariableFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+/// Value returned by a variable getter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VariableValue<'a> {
+    /// Data which lives at least as long as the request, e.g. in a request context or the
+    /// request pool.
+    Borrowed(&'a [u8]),
+    /// Data which is copied to the request pool.
+    Owned(Vec<u8>),
+}
+
+impl<'a> From<&'a [u8]> for VariableValue<'a> {
+    fn from(value: &'a [u8]) -> Self {
+        VariableValue::Borrowed(value)
+    }
+}
+
+impl<'a> From<&'a str> for VariableValue<'a> {
+    fn from(value: &'a str) -> Self {
+        VariableValue::Borrowed(value.as_bytes())
+    }
+}
+
+impl<'a> From<&'a NgxStr> for VariableValue<'a> {
+    fn from(value: &'a NgxStr) -> Self {
+        VariableValue::Borrowed(value.as_bytes())
+    }
+}
+
+impl From<Vec<u8>> for VariableValue<'_> {
+    fn from(value: Vec<u8>) -> Self {
+        VariableValue::Owned(value)
+    }
+}
+
+impl From<String> for VariableValue<'_> {
+    fn from(value: String) -> Self {
+        VariableValue::Owned(value.into_bytes())
+    }
+}
+
+type VariableGetter = dyn for<'a> Fn(&'a mut Request, &NgxStr) -> Option<VariableValue<'a>>;
+type VariableSetter = dyn Fn(&mut Request, &NgxStr, &[u8]);
+
+/// Handlers of a variable, allocated from the configuration pool and passed as the variable data.
+struct VariableHandlers {
+    name: ngx_str_t,
+    get: Option<Box<VariableGetter>>,
+    set: Option<Box<VariableSetter>>,
+}
+
+/// Builder for an HTTP [variable] with Rust getter and setter closures.
+///
+/// Variables should be registered from
+/// [`HTTPModule::preconfiguration`](crate::http::HTTPModule::preconfiguration), so they are
+/// available to the directives of all modules.
+///
+/// ```rust,ignore
+/// unsafe extern "C" fn preconfiguration(cf: *mut ngx_conf_t) -> ngx_int_t {
+///     http::VariableBuilder::new("hello")
+///         .flags(http::VariableFlags::NOCACHEABLE)
+///         .getter(|_request: &mut http::Request| Some("world".into()))
+///         .register(&mut *cf)
+///         .into()
+/// }
+/// ```
+///
+/// [variable]: https://nginx.org/en/docs/dev/development_guide.html#http_variables
+pub struct VariableBuilder {
+    name: String,
+    flags: VariableFlags,
+    get: Option<Box<VariableGetter>>,
+    set: Option<Box<VariableSetter>>,
+}
+
+impl VariableBuilder {
+    /// Creates a builder for the variable `name`, without the leading `$`.
+    pub fn new(name: &str) -> Self {
+        VariableBuilder {
+            name: name.to_string(),
+            flags: VariableFlags::default(),
+            get: None,
+            set: None,
+        }
+    }
+
+    /// Adds `flags` to the variable flags, none by default.
+    pub fn flags(mut self, flags: VariableFlags) -> Self {
+        self.flags |= flags;
+        self
+    }
+
+    /// Sets the getter, which returns the variable value, or `None` if the variable is not found.
+    pub fn getter<F>(mut self, get: F) -> Self
+    where
+        F: for<'a> Fn(&'a mut Request) -> Option<VariableValue<'a>> + 'static,
+    {
+        self.get = Some(Box::new(move |request, _| get(request)));
+        self
+    }
+
+    /// Sets the getter of a prefix variable, which also takes the full name of the variable being
+    /// evaluated, and sets the [`VariableFlags::PREFIX`] flag.
+    ///
+    /// For example, a `cookie_` prefix variable matches `$cookie_name`, with the name `cookie_name`.
+    pub fn prefix_getter<F>(mut self, get: F) -> Self
+    where
+        F: for<'a> Fn(&'a mut Request, &NgxStr) -> Option<VariableValue<'a>> + 'static,
+    {
+        self.flags |= VariableFlags::PREFIX;
+        self.get = Some(Box::new(get));
+        self
+    }
+
+    /// Sets the setter, which is called when the variable is assigned, e.g. with the `set`
+    /// directive. This requires the [`VariableFlags::CHANGEABLE`] flag.
+    ///
+    /// The setter also takes the name of the variable, which matters for prefix variables.
+    pub fn setter<F>(mut self, set: F) -> Self
+    where
+        F: Fn(&mut Request, &NgxStr, &[u8]) + 'static,
+    {
+        self.set = Some(Box::new(set));
+        self
+    }
+
+    /// Registers the variable in the HTTP configuration.
+    pub fn register(self, cf: &mut ngx_conf_t) -> Status {
+        let mut name = ngx_str_t {
+            len: self.name.len(),
+            data: self.name.as_ptr() as *mut u_char,
+        };
+        // SAFETY: `ngx_http_add_variable` copies the name to the configuration pool.
+        let v = unsafe { ngx_http_add_variable(cf, &mut name, self.flags.0) };
+        if v.is_null() {
+            return Status::NGX_ERROR;
+        }
+        let v = unsafe { &mut *v };
+
+        let prefix = self.flags.contains(VariableFlags::PREFIX);
+        let handlers = VariableHandlers {
+            name: v.name,
+            get: self.get,
+            set: self.set,
+        };
+        if handlers.get.is_some() {
+            v.get_handler = Some(if prefix {
+                prefix_variable_get_handler
+            } else {
+                variable_get_handler
+            });
+        }
+        if handlers.set.is_some() {
+            v.set_handler = Some(if prefix {
+                prefix_variable_set_handler
+            } else {
+                variable_set_handler
+            });
+        }
+
+        // SAFETY: the handlers live as long as the configuration, and are dropped with its pool.
+        let mut pool = unsafe { Pool::from_ngx_pool(cf.pool) };
+        let handlers = pool.allocate(handlers);
+        if handlers.is_null() {
+            return Status::NGX_ERROR;
+        }
+        v.data = handlers as uintptr_t;
+        Status::NGX_OK
+    }
+}
+
+/// Get the index of a variable to evaluate it with [`Request::indexed_variable`].
+///
+/// This must be called at configuration time; indexed access is faster than evaluating the
+/// variable by name. Returns `None` if the index cannot be allocated; unknown variables are
+/// reported when the configuration is complete.
+pub fn get_variable_index(cf: &mut ngx_conf_t, name: &str) -> Option<ngx_uint_t> {
+    let mut name = ngx_str_t {
+        len: name.len(),
+        data: name.as_ptr() as *mut u_char,
+    };
+    // SAFETY: `ngx_http_get_variable_index` copies the name to the configuration pool.
+    let index = unsafe { ngx_http_get_variable_index(cf, &mut name) };
+    if index == NGX_ERROR as ngx_int_t {
+        return None;
+    }
+    Some(index as ngx_uint_t)
+}
+
+impl Request {
+    /// Evaluate the variable `name`, without the leading `$`.
+    ///
+    /// Returns `None` if the variable is not defined or not found.
+    pub fn variable(&mut self, name: &str) -> Option<&NgxStr> {
+        let mut pool = self.pool();
+        let data = pool.alloc(name.len()) as *mut u_char;
+        if data.is_null() {
+            return None;
+        }
+        // SAFETY: the lowercase name is allocated from the request pool, as the value of a prefix
+        // variable may refer to it.
+        unsafe {
+            let key = ngx_hash_strlow(data, name.as_ptr() as *mut u_char, name.len());
+            let mut name = ngx_str_t { len: name.len(), data };
+            variable_value(ngx_http_get_variable(self.into(), &mut name, key))
+        }
+    }
+
+    /// Evaluate the variable at `index`, as returned by [`get_variable_index`].
+    ///
+    /// Returns `None` if the variable is not found.
+    pub fn indexed_variable(&mut self, index: ngx_uint_t) -> Option<&NgxStr> {
+        unsafe { variable_value(ngx_http_get_indexed_variable(self.into(), index)) }
+    }
+
+    /// Evaluate the variable at `index`, as returned by [`get_variable_index`], ignoring the
+    /// cached value of a [`VariableFlags::NOCACHEABLE`] variable.
+    ///
+    /// Returns `None` if the variable is not found.
+    pub fn flushed_variable(&mut self, index: ngx_uint_t) -> Option<&NgxStr> {
+        unsafe { variable_value(ngx_http_get_flushed_variable(self.into(), index)) }
+    }
+}
+
+unsafe fn variable_value<'a>(vv: *mut ngx_http_variable_value_t) -> Option<&'a NgxStr> {
+    if vv.is_null() || (*vv).not_found() != 0 {
+        return None;
+    }
+    Some(NgxStr::from_ngx_str(ngx_str_t {
+        len: (*vv).len() as usize,
+        data: (*vv).data,
+    }))
+}
+
+/// Find the handlers of the prefix variable matching `name`.
+///
+/// This follows the rule of NGINX when resolving a prefix variable: each prefix is only compared to
+/// the length of the name, so the last matching prefix in declaration order is selected, which is
+/// the variable whose handler is being called. The data of other prefix variables is not
+/// [`VariableHandlers`], so the handlers of the variable are checked first.
+unsafe fn prefix_variable_handlers<'a>(r: *mut ngx_http_request_t, name: &ngx_str_t) -> Option<&'a VariableHandlers> {
+    let cmcf = *(*r).main_conf.add(ngx_http_core_module.ctx_index) as *const ngx_http_core_main_conf_t;
+    let pv = &(*cmcf).prefix_variables;
+    let vars = std::slice::from_raw_parts(pv.elts as *const ngx_http_variable_t, pv.nelts);
+    let name = std::slice::from_raw_parts(name.data, name.len);
+
+    let mut len = 0;
+    let mut matched: Option<&ngx_http_variable_t> = None;
+    for v in vars {
+        let prefix = std::slice::from_raw_parts(v.name.data, v.name.len);
+        if name.len() > len && name.starts_with(prefix) {
+            len = v.name.len;
+            matched = Some(v);
+        }
+    }
+
+    let v = matched?;
+    let get = v.get_handler.map(|h| h as *const ());
+    let set = v.set_handler.map(|h| h as *const ());
+    if get != Some(prefix_variable_get_handler as *const ()) && set != Some(prefix_variable_set_handler as *const ()) {
+        return None;
+    }
+    (v.data as *const VariableHandlers).as_ref()
+}
+
+unsafe fn get_variable(
+    r: *mut ngx_http_request_t,
+    v: *mut ngx_http_variable_value_t,
+    handlers: Option<&VariableHandlers>,
+    name: ngx_str_t,
+) -> ngx_int_t {
+    let Some(get) = handlers.and_then(|h| h.get.as_ref()) else {
+        (*v).set_not_found(1);
+        return Status::NGX_OK.into();
+    };
+    let request = Request::from_ngx_http_request(r);
+    let (data, len) = match get(request, NgxStr::from_ngx_str(name)) {
+        None => {
+            (*v).set_not_found(1);
+            return Status::NGX_OK.into();
+        }
+        Some(VariableValue::Borrowed(value)) => (value.as_ptr() as *mut u_char, value.len()),
+        Some(VariableValue::Owned(value)) => {
+            let data = Pool::from_ngx_pool((*r).pool).alloc(value.len()) as *mut u_char;
+            if data.is_null() {
+                return Status::NGX_ERROR.into();
+            }
+            ptr::copy_nonoverlapping(value.as_ptr(), data, value.len());
+            (data, value.len())
+        }
+    };
+    (*v).set_len(len as _);
+    (*v).set_valid(1);
+    (*v).set_no_cacheable(0);
+    (*v).set_not_found(0);
+    (*v).data = data;
+    Status::NGX_OK.into()
+}
+
+unsafe fn set_variable(
+    r: *mut ngx_http_request_t,
+    v: *mut ngx_http_variable_value_t,
+    handlers: Option<&VariableHandlers>,
+    name: ngx_str_t,
+) {
+    if let Some(set) = handlers.and_then(|h| h.set.as_ref()) {
+        let value = std::slice::from_raw_parts((*v).data, (*v).len() as usize);
+        set(Request::from_ngx_http_request(r), NgxStr::from_ngx_str(name), value);
+    }
+}
+
+unsafe extern "C" fn variable_get_handler(
+    r: *mut ngx_http_request_t,
+    v: *mut ngx_http_variable_value_t,
+    data: uintptr_t,
+) -> ngx_int_t {
+    let handlers = (data as *const VariableHandlers).as_ref();
+    let name = handlers.map_or(ngx_null_string!(), |h| h.name);
+    get_variable(r, v, handlers, name)
+}
+
+unsafe extern "C" fn variable_set_handler(
+    r: *mut ngx_http_request_t,
+    v: *mut ngx_http_variable_value_t,
+    data: uintptr_t,
+) {
+    let handlers = (data as *const VariableHandlers).as_ref();
+    let name = handlers.map_or(ngx_null_string!(), |h| h.name);
+    set_variable(r, v, handlers, name)
+}
+
+// NGINX passes the name of the evaluated variable to prefix variable handlers instead of the
+// variable data.
+
+unsafe extern "C" fn prefix_variable_get_handler(
+    r: *mut ngx_http_request_t,
+    v: *mut ngx_http_variable_value_t,
+    data: uintptr_t,
+) -> ngx_int_t {
+    let name = *(data as *const ngx_str_t);
+    let handlers = prefix_variable_handlers(r, &name);
+    get_variable(r, v, handlers, name)
+}
+
+unsafe extern "C" fn prefix_variable_set_handler(
+    r: *mut ngx_http_request_t,
+    v: *mut ngx_http_variable_value_t,
+    data: uintptr_t,
+) {
+    let name = *(data as *const ngx_str_t);
+    let handlers = prefix_variable_handlers(r, &name);
+    set_variable(r, v, handlers, name)
+}
@@ -18,6 +18,8 @@
 //!
 //! To build Linux-only modules, use the "linux" feature: `cargo build --package=examples --examples --features=linux --release`
 //!
+//! To build NGINX with the mail proxy and enable the [`mail`](crate::mail) module, use the "mail" feature of this crate.
+//!
 //! After compilation, the modules can be found in the path `target/release/examples/` ( with the `.so` file extension for
 //! Linux or `.dylib` for MacOS).
 //!
@@ -40,6 +42,12 @@
 /// utilities will generally align with the NGINX 'core' files and APIs.
 pub mod core;
 
+/// The async module.
+///
+/// This module provides an executor for Rust futures driven by the NGINX event loop, tied to
+/// HTTP requests, and futures for NGINX timers.
+pub mod async_;
+
 /// The ffi module.
 ///
 /// This module provides scoped FFI bindings for NGINX symbols.
@@ -51,6 +59,19 @@ pub mod ffi;
 /// configuration access, and statuses.
 pub mod http;
 
+/// The stream module.
+///
+/// This module provides wrappers and utilities to NGINX stream (TCP/UDP) APIs, such as sessions,
+/// configuration access, and phase handlers.
+pub mod stream;
+
+/// The mail module.
+///
+/// This module provides wrappers and utilities to NGINX mail proxy APIs, such as sessions and
+/// configuration access. Requires the `mail` feature.
+#[cfg(feature = "mail")]
+pub mod mail;
+
 /// The log module.
 ///
 /// This module provides an interface into the NGINX logger framework.
@@ -60,23 +81,40 @@ pub mod log;
 ///
 /// These are normally generated by the Nginx module system, but need to be
 /// defined when building modules outside of it.
+///
+/// With `before = "module_name"`, the modules are loaded before that NGINX module, as done for
+/// `HTTP_FILTER` modules by the NGINX build, which are loaded before
+/// `ngx_http_copy_filter_module`.
 #[macro_export]
 macro_rules! ngx_modules {
     ($( $mod:ident ),+) => {
+        $crate::ngx_modules!(@modules $( $mod ),+);
+
         #[no_mangle]
-        pub static mut ngx_modules: [*const ngx_module_t; $crate::count!($( $mod, )+) + 1] = [
-            $( unsafe { &$mod } as *const ngx_module_t, )+
+        pub static mut ngx_module_order: [*const ::std::os::raw::c_char; 1] = [
             std::ptr::null()
         ];
+    };
+    ($( $mod:ident ),+ ; before = $before:literal) => {
+        $crate::ngx_modules!(@modules $( $mod ),+);
 
         #[no_mangle]
-        pub static mut ngx_module_names: [*const c_char; $crate::count!($( $mod, )+) + 1] = [
-            $( concat!(stringify!($mod), "\0").as_ptr() as *const i8, )+
+        pub static mut ngx_module_order: [*const ::std::os::raw::c_char; $crate::count!($( $mod, )+) + 2] = [
+            $( concat!(stringify!($mod), "\0").as_ptr() as *const ::std::os::raw::c_char, )+
+            concat!($before, "\0").as_ptr() as *const ::std::os::raw::c_char,
+            std::ptr::null()
+        ];
+    };
+    (@modules $( $mod:ident ),+) => {
+        #[no_mangle]
+        pub static mut ngx_modules: [*const $crate::ffi::ngx_module_t; $crate::count!($( $mod, )+) + 1] = [
+            $( unsafe { &$mod } as *const $crate::ffi::ngx_module_t, )+
             std::ptr::null()
         ];
 
         #[no_mangle]
-        pub static mut ngx_module_order: [*const c_char; 1] = [
+        pub static mut ngx_module_names: [*const ::std::os::raw::c_char; $crate::count!($( $mod, )+) + 1] = [
+            $( concat!(stringify!($mod), "\0").as_ptr() as *const ::std::os::raw::c_char, )+
             std::ptr::null()
         ];
     };
@@ -86,5 +124,5 @@ macro_rules! ngx_modules {
 #[macro_export]
 macro_rules! count {
     () => { 0usize };
-    ($x:tt, $( $xs:tt ),*) => { 1usize + $crate::count!($( $xs, )*) };
+    ($x:tt, $( $xs:tt ),* $(,)?) => { 1usize + $crate::count!($( $xs, )*) };
 }
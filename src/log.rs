@@ -27,6 +27,25 @@ macro_rules! ngx_log_debug {
     }
 }
 
+/// Write to logger at a specified level, such as `NGX_LOG_ERR`, if enabled for the log.
+///
+/// See [Logging](https://nginx.org/en/docs/dev/development_guide.html#logging)
+/// for available log levels.
+#[macro_export]
+macro_rules! ngx_log_error {
+    ( $level:expr, $log:expr, $($arg:tt)* ) => {
+        let log = $log;
+        let level = $level as $crate::ffi::ngx_uint_t;
+        if level <= unsafe { (*log).log_level } {
+            let fmt = ::std::ffi::CString::new("%s").unwrap();
+            let c_message = ::std::ffi::CString::new(format!($($arg)*)).unwrap();
+            unsafe {
+                $crate::ffi::ngx_log_error_core(level, log, 0, fmt.as_ptr(), c_message.as_ptr());
+            }
+        }
+    }
+}
+
 /// Log to request connection log at level [`NGX_LOG_DEBUG_HTTP`].
 ///
 /// [`NGX_LOG_DEBUG_HTTP`]: https://nginx.org/en/docs/dev/development_guide.html#logging
@@ -38,6 +57,17 @@ macro_rules! ngx_log_debug_http {
     }
 }
 
+/// Log to stream session connection log at level [`NGX_LOG_DEBUG_STREAM`].
+///
+/// [`NGX_LOG_DEBUG_STREAM`]: https://nginx.org/en/docs/dev/development_guide.html#logging
+#[macro_export]
+macro_rules! ngx_log_debug_stream {
+    ( $session:expr, $($arg:tt)* ) => {
+        let log = unsafe { (*$session.connection()).log };
+        $crate::ngx_log_debug!(log, $($arg)*);
+    }
+}
+
 /// Debug masks for use with ngx_log_debug_mask, these represent the only accepted values for the
 /// mask.
 #[derive(Debug)]
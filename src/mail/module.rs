@@ -0,0 +1,129 @@
+use crate::core::NGX_CONF_ERROR;
+use crate::core::*;
+use crate::ffi::*;
+
+use core::ptr;
+use std::os::raw::{c_char, c_void};
+
+/// Define a mail module from a [`MailModule`] implementation.
+///
+/// Generates the `ngx_module_t` static named `$name` with its `ngx_mail_module_t` context and
+/// exports it with [`ngx_modules`](crate::ngx_modules). The version and signature fields are taken
+/// from the NGINX the crate is built against.
+///
+/// Optional arguments:
+/// * `commands` - a null-terminated `ngx_command_t` array.
+///
+/// ```rust,ignore
+/// ngx_mail_module!(ngx_mail_hello_module, Module, commands = ngx_mail_hello_commands);
+/// ```
+#[macro_export]
+macro_rules! ngx_mail_module {
+    (
+        $name:ident, $module:ty
+        $(, commands = $commands:ident)?
+        $(,)?
+    ) => {
+        $crate::ngx_modules!($name);
+
+        #[no_mangle]
+        pub static mut $name: $crate::ffi::ngx_module_t = $crate::ffi::ngx_module_t {
+            ctx_index: $crate::ffi::ngx_uint_t::MAX,
+            index: $crate::ffi::ngx_uint_t::MAX,
+            name: ::std::ptr::null_mut(),
+            spare0: 0,
+            spare1: 0,
+            version: $crate::ffi::nginx_version as $crate::ffi::ngx_uint_t,
+            signature: $crate::ffi::NGX_RS_MODULE_SIGNATURE.as_ptr() as *const ::std::os::raw::c_char,
+
+            ctx: {
+                static CTX: $crate::ffi::ngx_mail_module_t = $crate::ffi::ngx_mail_module_t {
+                    protocol: ::std::ptr::null_mut(),
+                    create_main_conf: Some(<$module as $crate::mail::MailModule>::create_main_conf),
+                    init_main_conf: Some(<$module as $crate::mail::MailModule>::init_main_conf),
+                    create_srv_conf: Some(<$module as $crate::mail::MailModule>::create_srv_conf),
+                    merge_srv_conf: Some(<$module as $crate::mail::MailModule>::merge_srv_conf),
+                };
+                &CTX as *const _ as *mut _
+            },
+            commands: $crate::ngx_mail_module!(@commands $($commands)?),
+            type_: $crate::ffi::NGX_MAIL_MODULE as $crate::ffi::ngx_uint_t,
+
+            init_master: None,
+            init_module: None,
+            init_process: None,
+            init_thread: None,
+            exit_thread: None,
+            exit_process: None,
+            exit_master: None,
+
+            spare_hook0: 0,
+            spare_hook1: 0,
+            spare_hook2: 0,
+            spare_hook3: 0,
+            spare_hook4: 0,
+            spare_hook5: 0,
+            spare_hook6: 0,
+            spare_hook7: 0,
+        };
+    };
+    (@commands) => {
+        ::std::ptr::null_mut()
+    };
+    (@commands $commands:ident) => {
+        unsafe { ::std::ptr::addr_of_mut!($commands) as *mut $crate::ffi::ngx_command_t }
+    };
+}
+
+/// The `MailModule` trait provides the NGINX configuration stage interface for mail modules.
+///
+/// These functions allocate structures, initialize them, and merge through the configuration
+/// layers. Unlike HTTP and stream modules, mail modules have no pre- and postconfiguration
+/// stages.
+///
+/// See https://nginx.org/en/docs/dev/development_guide.html#adding_new_modules for details.
+pub trait MailModule {
+    /// Configuration in the `mail` block.
+    type MainConf: Merge + Default;
+    /// Configuration in a `server` block within the `mail` block.
+    type SrvConf: Merge + Default;
+
+    /// # Safety
+    ///
+    /// Callers should provide valid non-null `ngx_conf_t` arguments. Implementers must
+    /// guard against null inputs or risk runtime errors.
+    unsafe extern "C" fn create_main_conf(cf: *mut ngx_conf_t) -> *mut c_void {
+        let mut pool = Pool::from_ngx_pool((*cf).pool);
+        pool.allocate::<Self::MainConf>(Default::default()) as *mut c_void
+    }
+
+    /// # Safety
+    ///
+    /// Callers should provide valid non-null `ngx_conf_t` arguments. Implementers must
+    /// guard against null inputs or risk runtime errors.
+    unsafe extern "C" fn init_main_conf(_cf: *mut ngx_conf_t, _conf: *mut c_void) -> *mut c_char {
+        ptr::null_mut()
+    }
+
+    /// # Safety
+    ///
+    /// Callers should provide valid non-null `ngx_conf_t` arguments. Implementers must
+    /// guard against null inputs or risk runtime errors.
+    unsafe extern "C" fn create_srv_conf(cf: *mut ngx_conf_t) -> *mut c_void {
+        let mut pool = Pool::from_ngx_pool((*cf).pool);
+        pool.allocate::<Self::SrvConf>(Default::default()) as *mut c_void
+    }
+
+    /// # Safety
+    ///
+    /// Callers should provide valid non-null `ngx_conf_t` arguments. Implementers must
+    /// guard against null inputs or risk runtime errors.
+    unsafe extern "C" fn merge_srv_conf(_cf: *mut ngx_conf_t, prev: *mut c_void, conf: *mut c_void) -> *mut c_char {
+        let prev = &mut *(prev as *mut Self::SrvConf);
+        let conf = &mut *(conf as *mut Self::SrvConf);
+        match conf.merge(prev) {
+            Ok(_) => ptr::null_mut(),
+            Err(_) => NGX_CONF_ERROR as _,
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use crate::core::*;
+use crate::ffi::*;
+
+use std::fmt;
+use std::os::raw::c_void;
+
+/// Mail protocol of a [`MailSession`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MailProtocol {
+    /// POP3
+    Pop3,
+    /// IMAP
+    Imap,
+    /// SMTP
+    Smtp,
+}
+
+impl MailProtocol {
+    /// Protocol name as sent in the `Auth-Protocol` header of `auth_http` requests.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MailProtocol::Pop3 => "pop3",
+            MailProtocol::Imap => "imap",
+            MailProtocol::Smtp => "smtp",
+        }
+    }
+}
+
+/// Wrapper struct for an `ngx_mail_session_t` pointer, providing methods for working with mail
+/// proxy sessions.
+#[repr(transparent)]
+pub struct MailSession(ngx_mail_session_t);
+
+impl<'a> From<&'a MailSession> for *const ngx_mail_session_t {
+    fn from(session: &'a MailSession) -> Self {
+        &session.0 as *const _
+    }
+}
+impl<'a> From<&'a mut MailSession> for *mut ngx_mail_session_t {
+    fn from(session: &'a mut MailSession) -> Self {
+        &session.0 as *const _ as *mut _
+    }
+}
+
+impl MailSession {
+    /// Create a [`MailSession`] from an [`ngx_mail_session_t`].
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null pointer to a valid `ngx_mail_session_t`
+    /// which shares the same representation as `MailSession`.
+    pub unsafe fn from_ngx_mail_session<'a>(s: *mut ngx_mail_session_t) -> &'a mut MailSession {
+        &mut *s.cast::<MailSession>()
+    }
+
+    /// Pointer to a [`ngx_connection_t`] client connection object.
+    ///
+    /// [`ngx_connection_t`]: https://nginx.org/en/docs/dev/development_guide.html#connection
+    pub fn connection(&self) -> *mut ngx_connection_t {
+        self.0.connection
+    }
+
+    /// Pointer to a [`ngx_log_t`].
+    ///
+    /// [`ngx_log_t`]: https://nginx.org/en/docs/dev/development_guide.html#logging
+    pub fn log(&self) -> *mut ngx_log_t {
+        unsafe { (*self.connection()).log }
+    }
+
+    /// Session pool, which is the client connection pool.
+    pub fn pool(&self) -> Pool {
+        // SAFETY: The session is allocated from the connection pool, thus it must be a valid pool.
+        unsafe { Pool::from_ngx_pool((*self.connection()).pool) }
+    }
+
+    /// Mail protocol of the session.
+    pub fn protocol(&self) -> MailProtocol {
+        match self.0.protocol() {
+            NGX_MAIL_IMAP_PROTOCOL => MailProtocol::Imap,
+            NGX_MAIL_SMTP_PROTOCOL => MailProtocol::Smtp,
+            _ => MailProtocol::Pop3,
+        }
+    }
+
+    /// Authentication method used by the client, such as `NGX_MAIL_AUTH_PLAIN`.
+    pub fn auth_method(&self) -> ngx_uint_t {
+        self.0.auth_method() as ngx_uint_t
+    }
+
+    /// Login provided by the client.
+    pub fn login(&self) -> &NgxStr {
+        unsafe { NgxStr::from_ngx_str(self.0.login) }
+    }
+
+    /// Password provided by the client.
+    pub fn passwd(&self) -> &NgxStr {
+        unsafe { NgxStr::from_ngx_str(self.0.passwd) }
+    }
+
+    /// Client address as text.
+    pub fn addr_text(&self) -> Option<&NgxStr> {
+        if self.0.addr_text.is_null() {
+            return None;
+        }
+        unsafe { Some(NgxStr::from_ngx_str(*self.0.addr_text)) }
+    }
+
+    /// Client hostname, if resolved.
+    pub fn host(&self) -> &NgxStr {
+        unsafe { NgxStr::from_ngx_str(self.0.host) }
+    }
+
+    /// Number of the login attempt within the session.
+    pub fn login_attempt(&self) -> ngx_uint_t {
+        self.0.login_attempt
+    }
+
+    /// Module main configuration.
+    pub fn get_module_main_conf<T>(&self, module: &ngx_module_t) -> Option<&T> {
+        let mc = unsafe { *self.0.main_conf.add(module.ctx_index) } as *mut T;
+        if mc.is_null() {
+            return None;
+        }
+        Some(unsafe { &*mc })
+    }
+
+    /// Module server configuration.
+    pub fn get_module_srv_conf<T>(&self, module: &ngx_module_t) -> Option<&T> {
+        let sc = unsafe { *self.0.srv_conf.add(module.ctx_index) } as *mut T;
+        if sc.is_null() {
+            return None;
+        }
+        Some(unsafe { &*sc })
+    }
+
+    /// Get Module context pointer
+    fn get_module_ctx_ptr(&self, module: &ngx_module_t) -> *mut c_void {
+        unsafe { *self.0.ctx.add(module.ctx_index) }
+    }
+
+    /// Get Module context
+    pub fn get_module_ctx<T>(&self, module: &ngx_module_t) -> Option<&T> {
+        let cf = self.get_module_ctx_ptr(module) as *mut T;
+
+        if cf.is_null() {
+            return None;
+        }
+        let co = unsafe { &*cf };
+        Some(co)
+    }
+
+    /// Sets the value as the module's context.
+    pub fn set_module_ctx(&self, value: *mut c_void, module: &ngx_module_t) {
+        unsafe {
+            *self.0.ctx.add(module.ctx_index) = value;
+        };
+    }
+
+    /// Close the client connection and free the session.
+    ///
+    /// # Safety
+    ///
+    /// The session is allocated from the connection pool, which is destroyed: the caller must
+    /// not use this session, nor any reference or pointer derived from it, after this call.
+    pub unsafe fn close(&mut self) {
+        unsafe { ngx_mail_close_connection(self.connection()) }
+    }
+}
+
+impl fmt::Debug for MailSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MailSession").field("session_", &self.0).finish()
+    }
+}
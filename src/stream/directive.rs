@@ -0,0 +1,90 @@
+use crate::ffi::*;
+
+use std::ops::BitOr;
+
+/// Define a null-terminated array of stream configuration directives.
+///
+/// Each entry is a directive as accepted by [`ngx_stream_command`]. The terminating null
+/// directive is added automatically.
+///
+/// ```rust,ignore
+/// ngx_stream_commands! {
+///     #[no_mangle]
+///     static mut ngx_stream_deny_commands = [
+///         {
+///             name: "stream_deny",
+///             context: [MAIN, SRV],
+///             args: [FLAG],
+///             conf: SRV,
+///             slot: FlagSlot => (SrvConfig, deny),
+///         },
+///     ];
+/// }
+/// ```
+#[macro_export]
+macro_rules! ngx_stream_commands {
+    (
+        $(#[$attr:meta])*
+        $vis:vis static mut $name:ident = [
+            $( { $($directive:tt)* } ),+ $(,)?
+        ];
+    ) => {
+        $(#[$attr])*
+        $vis static mut $name: [$crate::ffi::ngx_command_t; $crate::count!($( { $($directive)* }, )+) + 1] = [
+            $( $crate::ngx_stream_command!({ $($directive)* }), )+
+            $crate::ffi::ngx_command_t {
+                name: $crate::ngx_null_string!(),
+                type_: 0,
+                set: None,
+                conf: 0,
+                offset: 0,
+                post: ::std::ptr::null_mut(),
+            },
+        ];
+    };
+}
+
+/// Define a single stream configuration directive ([`ngx_command_t`]).
+///
+/// Accepts the same `set` and `slot` forms as [`ngx_http_command`](crate::ngx_http_command), with
+/// the stream configuration blocks ([`DirectiveContext`]) and levels ([`DirectiveConf`]).
+///
+/// [`ngx_command_t`]: https://nginx.org/en/docs/dev/development_guide.html#config_directives
+#[macro_export]
+macro_rules! ngx_stream_command {
+    ({ $($directive:tt)* }) => {
+        $crate::ngx_conf_command!($crate::stream::DirectiveContext, $crate::stream::DirectiveConf, { $($directive)* })
+    };
+}
+
+/// Configuration blocks a stream directive may appear in.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct DirectiveContext(pub ngx_uint_t);
+
+impl DirectiveContext {
+    /// The `stream` block.
+    pub const MAIN: DirectiveContext = DirectiveContext(NGX_STREAM_MAIN_CONF as ngx_uint_t);
+    /// A `server` block within the `stream` block.
+    pub const SRV: DirectiveContext = DirectiveContext(NGX_STREAM_SRV_CONF as ngx_uint_t);
+    /// An `upstream` block within the `stream` block.
+    pub const UPS: DirectiveContext = DirectiveContext(NGX_STREAM_UPS_CONF as ngx_uint_t);
+}
+
+impl BitOr for DirectiveContext {
+    type Output = DirectiveContext;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        DirectiveContext(self.0 | rhs.0)
+    }
+}
+
+/// Configuration level a stream directive stores its value in.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct DirectiveConf(pub ngx_uint_t);
+
+impl DirectiveConf {
+    /// The module main configuration (`StreamModule::MainConf`).
+    pub const MAIN: DirectiveConf = DirectiveConf(NGX_RS_STREAM_MAIN_CONF_OFFSET);
+    /// The module server configuration (`StreamModule::SrvConf`).
+    pub const SRV: DirectiveConf = DirectiveConf(NGX_RS_STREAM_SRV_CONF_OFFSET);
+}
@@ -0,0 +1,9 @@
+mod conf;
+mod directive;
+mod module;
+mod session;
+
+pub use conf::*;
+pub use directive::*;
+pub use module::*;
+pub use session::*;
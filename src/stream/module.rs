@@ -0,0 +1,225 @@
+use crate::core::NGX_CONF_ERROR;
+use crate::core::*;
+use crate::ffi::*;
+use crate::stream::{ngx_stream_conf_get_module_main_conf, ngx_stream_conf_get_module_srv_conf};
+
+use core::ptr;
+use std::os::raw::{c_char, c_void};
+
+/// Define a stream module from a [`StreamModule`] implementation.
+///
+/// Generates the `ngx_module_t` static named `$name` with its `ngx_stream_module_t` context and
+/// exports it with [`ngx_modules`](crate::ngx_modules). The version and signature fields are taken
+/// from the NGINX the crate is built against.
+///
+/// Optional arguments:
+/// * `commands` - a null-terminated `ngx_command_t` array.
+/// * `variables` - a null-terminated `ngx_stream_variable_t` array. The variables are added in the
+///   preconfiguration stage, before calling [`StreamModule::preconfiguration`].
+///
+/// ```rust,ignore
+/// ngx_stream_module!(ngx_stream_hello_module, Module, commands = ngx_stream_hello_commands);
+/// ```
+#[macro_export]
+macro_rules! ngx_stream_module {
+    (
+        $name:ident, $module:ty
+        $(, commands = $commands:ident)?
+        $(, variables = $variables:ident)?
+        $(,)?
+    ) => {
+        $crate::ngx_modules!($name);
+
+        #[no_mangle]
+        pub static mut $name: $crate::ffi::ngx_module_t = $crate::ffi::ngx_module_t {
+            ctx_index: $crate::ffi::ngx_uint_t::MAX,
+            index: $crate::ffi::ngx_uint_t::MAX,
+            name: ::std::ptr::null_mut(),
+            spare0: 0,
+            spare1: 0,
+            version: $crate::ffi::nginx_version as $crate::ffi::ngx_uint_t,
+            signature: $crate::ffi::NGX_RS_MODULE_SIGNATURE.as_ptr() as *const ::std::os::raw::c_char,
+
+            ctx: {
+                static CTX: $crate::ffi::ngx_stream_module_t = $crate::ffi::ngx_stream_module_t {
+                    preconfiguration: Some($crate::ngx_stream_module!(@preconfiguration $module $(, $variables)?)),
+                    postconfiguration: Some(<$module as $crate::stream::StreamModule>::postconfiguration),
+                    create_main_conf: Some(<$module as $crate::stream::StreamModule>::create_main_conf),
+                    init_main_conf: Some(<$module as $crate::stream::StreamModule>::init_main_conf),
+                    create_srv_conf: Some(<$module as $crate::stream::StreamModule>::create_srv_conf),
+                    merge_srv_conf: Some(<$module as $crate::stream::StreamModule>::merge_srv_conf),
+                };
+                &CTX as *const _ as *mut _
+            },
+            commands: $crate::ngx_stream_module!(@commands $($commands)?),
+            type_: $crate::ffi::NGX_STREAM_MODULE as $crate::ffi::ngx_uint_t,
+
+            init_master: None,
+            init_module: None,
+            init_process: None,
+            init_thread: None,
+            exit_thread: None,
+            exit_process: None,
+            exit_master: None,
+
+            spare_hook0: 0,
+            spare_hook1: 0,
+            spare_hook2: 0,
+            spare_hook3: 0,
+            spare_hook4: 0,
+            spare_hook5: 0,
+            spare_hook6: 0,
+            spare_hook7: 0,
+        };
+    };
+    (@commands) => {
+        ::std::ptr::null_mut()
+    };
+    (@commands $commands:ident) => {
+        unsafe { ::std::ptr::addr_of_mut!($commands) as *mut $crate::ffi::ngx_command_t }
+    };
+    (@preconfiguration $module:ty) => {
+        <$module as $crate::stream::StreamModule>::preconfiguration
+    };
+    (@preconfiguration $module:ty, $variables:ident) => {{
+        unsafe extern "C" fn preconfiguration(cf: *mut $crate::ffi::ngx_conf_t) -> $crate::ffi::ngx_int_t {
+            let vars = ::std::ptr::addr_of_mut!($variables) as *mut $crate::ffi::ngx_stream_variable_t;
+            let rc = $crate::stream::add_variables(cf, vars);
+            if rc != $crate::core::Status::NGX_OK.into() {
+                return rc;
+            }
+            <$module as $crate::stream::StreamModule>::preconfiguration(cf)
+        }
+        preconfiguration
+    }};
+}
+
+/// Add a null-terminated array of variables to the stream configuration.
+///
+/// For each variable, the handlers and data are copied to the variable registered with
+/// `ngx_stream_add_variable`.
+///
+/// # Safety
+///
+/// Callers should provide a valid `ngx_conf_t` and a pointer to an array terminated by a
+/// variable with an empty name, such as [`ngx_stream_null_variable`](crate::ngx_stream_null_variable).
+pub unsafe fn add_variables(cf: *mut ngx_conf_t, mut vars: *mut ngx_stream_variable_t) -> ngx_int_t {
+    while (*vars).name.len != 0 {
+        let v = &mut *vars;
+        let var = ngx_stream_add_variable(cf, &mut v.name, v.flags);
+        if var.is_null() {
+            return Status::NGX_ERROR.into();
+        }
+        (*var).get_handler = v.get_handler;
+        (*var).set_handler = v.set_handler;
+        (*var).data = v.data;
+        vars = vars.add(1);
+    }
+    Status::NGX_OK.into()
+}
+
+/// Register a handler for a stream phase, such as `NGX_STREAM_PREREAD_PHASE` or
+/// `NGX_STREAM_ACCESS_PHASE`.
+///
+/// This is normally called from [`StreamModule::postconfiguration`].
+///
+/// See https://nginx.org/en/docs/dev/development_guide.html#stream_phases for details.
+///
+/// # Safety
+///
+/// Callers should provide a valid `ngx_conf_t` in the stream configuration context.
+pub unsafe fn add_phase_handler(
+    cf: *mut ngx_conf_t,
+    phase: ngx_stream_phases,
+    handler: ngx_stream_handler_pt,
+) -> Status {
+    let cmcf = ngx_stream_conf_get_module_main_conf(cf, &*ptr::addr_of!(ngx_stream_core_module));
+    let h = ngx_array_push(&mut (*cmcf).phases[phase as usize].handlers) as *mut ngx_stream_handler_pt;
+    if h.is_null() {
+        return Status::NGX_ERROR;
+    }
+    *h = handler;
+    Status::NGX_OK
+}
+
+/// Set the content handler of the current `server` block.
+///
+/// Stream content handlers are not registered in the `NGX_STREAM_CONTENT_PHASE` handlers, but
+/// installed by a directive in the `server` block, like `proxy_pass` or `return`.
+///
+/// # Safety
+///
+/// Callers should provide a valid `ngx_conf_t` in the stream `server` configuration context.
+pub unsafe fn set_content_handler(cf: *mut ngx_conf_t, handler: ngx_stream_content_handler_pt) {
+    let cscf = ngx_stream_conf_get_module_srv_conf(cf, &*ptr::addr_of!(ngx_stream_core_module))
+        as *mut ngx_stream_core_srv_conf_t;
+    (*cscf).handler = handler;
+}
+
+/// The `StreamModule` trait provides the NGINX configuration stage interface for stream modules.
+///
+/// These functions allocate structures, initialize them, and merge through the configuration
+/// layers.
+///
+/// See https://nginx.org/en/docs/dev/development_guide.html#adding_new_modules for details.
+pub trait StreamModule {
+    /// Configuration in the `stream` block.
+    type MainConf: Merge + Default;
+    /// Configuration in a `server` block within the `stream` block.
+    type SrvConf: Merge + Default;
+
+    /// # Safety
+    ///
+    /// Callers should provide valid non-null `ngx_conf_t` arguments. Implementers must
+    /// guard against null inputs or risk runtime errors.
+    unsafe extern "C" fn preconfiguration(_cf: *mut ngx_conf_t) -> ngx_int_t {
+        Status::NGX_OK.into()
+    }
+
+    /// # Safety
+    ///
+    /// Callers should provide valid non-null `ngx_conf_t` arguments. Implementers must
+    /// guard against null inputs or risk runtime errors.
+    unsafe extern "C" fn postconfiguration(_cf: *mut ngx_conf_t) -> ngx_int_t {
+        Status::NGX_OK.into()
+    }
+
+    /// # Safety
+    ///
+    /// Callers should provide valid non-null `ngx_conf_t` arguments. Implementers must
+    /// guard against null inputs or risk runtime errors.
+    unsafe extern "C" fn create_main_conf(cf: *mut ngx_conf_t) -> *mut c_void {
+        let mut pool = Pool::from_ngx_pool((*cf).pool);
+        pool.allocate::<Self::MainConf>(Default::default()) as *mut c_void
+    }
+
+    /// # Safety
+    ///
+    /// Callers should provide valid non-null `ngx_conf_t` arguments. Implementers must
+    /// guard against null inputs or risk runtime errors.
+    unsafe extern "C" fn init_main_conf(_cf: *mut ngx_conf_t, _conf: *mut c_void) -> *mut c_char {
+        ptr::null_mut()
+    }
+
+    /// # Safety
+    ///
+    /// Callers should provide valid non-null `ngx_conf_t` arguments. Implementers must
+    /// guard against null inputs or risk runtime errors.
+    unsafe extern "C" fn create_srv_conf(cf: *mut ngx_conf_t) -> *mut c_void {
+        let mut pool = Pool::from_ngx_pool((*cf).pool);
+        pool.allocate::<Self::SrvConf>(Default::default()) as *mut c_void
+    }
+
+    /// # Safety
+    ///
+    /// Callers should provide valid non-null `ngx_conf_t` arguments. Implementers must
+    /// guard against null inputs or risk runtime errors.
+    unsafe extern "C" fn merge_srv_conf(_cf: *mut ngx_conf_t, prev: *mut c_void, conf: *mut c_void) -> *mut c_char {
+        let prev = &mut *(prev as *mut Self::SrvConf);
+        let conf = &mut *(conf as *mut Self::SrvConf);
+        match conf.merge(prev) {
+            Ok(_) => ptr::null_mut(),
+            Err(_) => NGX_CONF_ERROR as _,
+        }
+    }
+}
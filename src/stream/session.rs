@@ -0,0 +1,197 @@
+use crate::core::*;
+use crate::ffi::*;
+
+use std::fmt;
+use std::os::raw::c_void;
+
+/// Define a static stream phase handler.
+///
+/// Handlers are expected to take a single [`StreamSession`] argument and return a [`Status`].
+/// See [`add_phase_handler`](crate::stream::add_phase_handler).
+#[macro_export]
+macro_rules! stream_session_handler {
+    ( $name: ident, $handler: expr ) => {
+        #[no_mangle]
+        extern "C" fn $name(s: *mut $crate::ffi::ngx_stream_session_t) -> $crate::ffi::ngx_int_t {
+            let status: $crate::core::Status =
+                $handler(unsafe { $crate::stream::StreamSession::from_ngx_stream_session(s) });
+            status.0
+        }
+    };
+}
+
+/// Define a static stream content handler.
+///
+/// Handlers are expected to take a single [`StreamSession`] argument and finalize the session
+/// when done. See [`set_content_handler`](crate::stream::set_content_handler).
+#[macro_export]
+macro_rules! stream_content_handler {
+    ( $name: ident, $handler: expr ) => {
+        #[no_mangle]
+        extern "C" fn $name(s: *mut $crate::ffi::ngx_stream_session_t) {
+            $handler(unsafe { $crate::stream::StreamSession::from_ngx_stream_session(s) });
+        }
+    };
+}
+
+/// Define a static stream variable setter.
+///
+/// The set handler expects a [`StreamSession`], [`mut ngx_variable_value_t`], and a [`usize`].
+/// Variables: <https://nginx.org/en/docs/dev/development_guide.html#stream_variables>
+#[macro_export]
+macro_rules! stream_variable_set {
+    ( $name: ident, $handler: expr ) => {
+        #[no_mangle]
+        unsafe extern "C" fn $name(
+            s: *mut $crate::ffi::ngx_stream_session_t,
+            v: *mut $crate::ffi::ngx_stream_variable_value_t,
+            data: usize,
+        ) {
+            $handler(
+                unsafe { $crate::stream::StreamSession::from_ngx_stream_session(s) },
+                v,
+                data,
+            );
+        }
+    };
+}
+
+/// Define a static stream variable evaluator.
+///
+/// Variable evaluators accept a [`StreamSession`] input argument and two output
+/// arguments: [`ngx_variable_value_t`] and [`usize`].
+/// Variables: <https://nginx.org/en/docs/dev/development_guide.html#stream_variables>
+#[macro_export]
+macro_rules! stream_variable_get {
+    ( $name: ident, $handler: expr ) => {
+        #[no_mangle]
+        unsafe extern "C" fn $name(
+            s: *mut $crate::ffi::ngx_stream_session_t,
+            v: *mut $crate::ffi::ngx_stream_variable_value_t,
+            data: usize,
+        ) -> $crate::ffi::ngx_int_t {
+            let status: $crate::core::Status = $handler(
+                unsafe { $crate::stream::StreamSession::from_ngx_stream_session(s) },
+                v,
+                data,
+            );
+            status.0
+        }
+    };
+}
+
+/// Wrapper struct for an `ngx_stream_session_t` pointer, providing methods for working with
+/// stream sessions.
+#[repr(transparent)]
+pub struct StreamSession(ngx_stream_session_t);
+
+impl<'a> From<&'a StreamSession> for *const ngx_stream_session_t {
+    fn from(session: &'a StreamSession) -> Self {
+        &session.0 as *const _
+    }
+}
+impl<'a> From<&'a mut StreamSession> for *mut ngx_stream_session_t {
+    fn from(session: &'a mut StreamSession) -> Self {
+        &session.0 as *const _ as *mut _
+    }
+}
+
+impl StreamSession {
+    /// Create a [`StreamSession`] from an [`ngx_stream_session_t`].
+    ///
+    /// [`ngx_stream_session_t`]: https://nginx.org/en/docs/dev/development_guide.html#stream_phases
+    ///
+    /// # Safety
+    ///
+    /// The caller has provided a valid non-null pointer to a valid `ngx_stream_session_t`
+    /// which shares the same representation as `StreamSession`.
+    pub unsafe fn from_ngx_stream_session<'a>(s: *mut ngx_stream_session_t) -> &'a mut StreamSession {
+        &mut *s.cast::<StreamSession>()
+    }
+
+    /// Pointer to a [`ngx_connection_t`] client connection object.
+    ///
+    /// [`ngx_connection_t`]: https://nginx.org/en/docs/dev/development_guide.html#connection
+    pub fn connection(&self) -> *mut ngx_connection_t {
+        self.0.connection
+    }
+
+    /// Pointer to a [`ngx_log_t`].
+    ///
+    /// [`ngx_log_t`]: https://nginx.org/en/docs/dev/development_guide.html#logging
+    pub fn log(&self) -> *mut ngx_log_t {
+        unsafe { (*self.connection()).log }
+    }
+
+    /// Session pool, which is the client connection pool.
+    pub fn pool(&self) -> Pool {
+        // SAFETY: The session is allocated from the connection pool, thus it must be a valid pool.
+        unsafe { Pool::from_ngx_pool((*self.connection()).pool) }
+    }
+
+    /// Number of bytes received from the client.
+    pub fn received(&self) -> off_t {
+        self.0.received
+    }
+
+    /// Module main configuration.
+    pub fn get_module_main_conf<T>(&self, module: &ngx_module_t) -> Option<&T> {
+        let mc = unsafe { *self.0.main_conf.add(module.ctx_index) } as *mut T;
+        if mc.is_null() {
+            return None;
+        }
+        Some(unsafe { &*mc })
+    }
+
+    /// Module server configuration.
+    pub fn get_module_srv_conf<T>(&self, module: &ngx_module_t) -> Option<&T> {
+        let sc = unsafe { *self.0.srv_conf.add(module.ctx_index) } as *mut T;
+        if sc.is_null() {
+            return None;
+        }
+        Some(unsafe { &*sc })
+    }
+
+    /// Get Module context pointer
+    fn get_module_ctx_ptr(&self, module: &ngx_module_t) -> *mut c_void {
+        unsafe { *self.0.ctx.add(module.ctx_index) }
+    }
+
+    /// Get Module context
+    pub fn get_module_ctx<T>(&self, module: &ngx_module_t) -> Option<&T> {
+        let cf = self.get_module_ctx_ptr(module) as *mut T;
+
+        if cf.is_null() {
+            return None;
+        }
+        let co = unsafe { &*cf };
+        Some(co)
+    }
+
+    /// Sets the value as the module's context.
+    pub fn set_module_ctx(&self, value: *mut c_void, module: &ngx_module_t) {
+        unsafe {
+            *self.0.ctx.add(module.ctx_index) = value;
+        };
+    }
+
+    /// Set the session status, e.g. `NGX_STREAM_OK` (200) or `NGX_STREAM_FORBIDDEN` (403).
+    ///
+    /// The status is available as the `$status` variable.
+    pub fn set_status(&mut self, status: ngx_uint_t) {
+        self.0.status = status;
+    }
+
+    /// Finalize the session with the given status.
+    ///
+    /// This is normally called by a content handler when the session is complete.
+    pub fn finalize(&mut self, status: ngx_uint_t) {
+        unsafe { ngx_stream_finalize_session(&mut self.0, status) }
+    }
+}
+
+impl fmt::Debug for StreamSession {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamSession").field("session_", &self.0).finish()
+    }
+}